@@ -1,5 +1,6 @@
 use bevy::prelude::*;
 
+use crate::collision_plugin::polygon_component::PolygonComponent;
 use crate::transform2d::Transform2d;
 
 #[derive(Component, Reflect)]
@@ -8,6 +9,10 @@ pub struct AABB
 {
     pub min: Vec2,
     pub max: Vec2,
+    /// World space box enclosing the tight one, used by the broad phase.
+    /// It is only refitted once the tight box moves out of it.
+    pub fat_min: Vec2,
+    pub fat_max: Vec2,
     pub draw_color: Color,
 }
 
@@ -45,6 +50,30 @@ impl AABB {
         return bounding_box;
     }
 
+    /// Bounding box of the rotated polygon relative to its translation, inflated by its radius
+    pub fn from_polygon(polygon: &PolygonComponent, transform: &Transform2d) -> Self
+    {
+        let mut bounding_box = Self::from(&polygon.get_rotated_points(transform));
+        let margin = Vec2::splat(polygon.radius * transform.scale);
+        bounding_box.min -= margin;
+        bounding_box.max += margin;
+        bounding_box.refit(transform, 0f32, Vec2::ZERO);
+        return bounding_box;
+    }
+
+    /// True if the tight box isn't contained in the fat one anymore
+    pub fn needs_refit(&self, transform: &Transform2d) -> bool {
+        let (min, max) = (transform.translate(self.min), transform.translate(self.max));
+        return min.cmplt(self.fat_min).any() || max.cmpgt(self.fat_max).any();
+    }
+
+    /// Enlarge the fat box around the tight one by `margin`, and stretch it along the predicted `displacement`
+    pub fn refit(&mut self, transform: &Transform2d, margin: f32, displacement: Vec2) {
+        let margin = Vec2::splat(margin);
+        self.fat_min = transform.translate(self.min) - margin + displacement.min(Vec2::ZERO);
+        self.fat_max = transform.translate(self.max) + margin + displacement.max(Vec2::ZERO);
+    }
+
     pub fn get_points(&self, t: &Transform2d) -> Vec<Vec3> {
         let min = self.min + t.translation;
         let max = self.max + t.translation;
@@ -63,6 +92,8 @@ impl Default for AABB {
         AABB {
             min: Vec2::default(),
             max: Vec2::default(),
+            fat_min: Vec2::default(),
+            fat_max: Vec2::default(),
             draw_color: Color::GRAY,
         }
     }
@@ -79,3 +110,40 @@ pub fn check_collision(a1: &AABB, t1: &Transform2d, a2: &AABB, t2: &Transform2d)
         a1_min.y <= a2_max.y &&
         a1_max.y >= a2_min.y;
 }
+
+/// Overlap test on world space bounds
+pub fn check_bounds_overlap(min_a: Vec2, max_a: Vec2, min_b: Vec2, max_b: Vec2) -> bool
+{
+    return min_a.x <= max_b.x &&
+        max_a.x >= min_b.x &&
+        min_a.y <= max_b.y &&
+        max_a.y >= min_b.y;
+}
+
+/// Slab test of the ray `origin + direction * t` against world space bounds, for t in [0, max_toi].
+/// Returns the t at which the ray enters the bounds, 0 if it starts inside.
+pub fn check_ray_overlap(origin: Vec2, direction: Vec2, max_toi: f32, min: Vec2, max: Vec2) -> Option<f32>
+{
+    let mut t_min = 0f32;
+    let mut t_max = max_toi;
+
+    for axis in 0..2 {
+        if direction[axis] == 0f32 {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let inv_direction = 1f32 / direction[axis];
+        let t_1 = (min[axis] - origin[axis]) * inv_direction;
+        let t_2 = (max[axis] - origin[axis]) * inv_direction;
+        t_min = t_min.max(t_1.min(t_2));
+        t_max = t_max.min(t_1.max(t_2));
+        if t_min > t_max {
+            return None;
+        }
+    }
+
+    return Some(t_min);
+}
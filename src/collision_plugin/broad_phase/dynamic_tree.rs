@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::collision_plugin::aabb::{check_bounds_overlap, check_ray_overlap};
+
+const NULL_NODE: usize = usize::MAX;
+
+// Below this depth, pair generation of the two children is done in parallel
+const PARALLEL_DEPTH: usize = 6;
+
+#[derive(Clone)]
+struct TreeNode {
+    min: Vec2,
+    max: Vec2,
+    parent: usize,
+    left: usize,
+    right: usize,
+    // Leaves have a height of 0, free nodes have a height of -1
+    height: i32,
+    entity: Option<Entity>,
+}
+
+impl TreeNode {
+    fn is_leaf(&self) -> bool {
+        return self.left == NULL_NODE;
+    }
+}
+
+/// Dynamic bounding volume hierarchy. Each leaf holds the world space AABB of an entity,
+/// and the tree is kept balanced with rotations on insertion and removal.
+pub struct DynamicTree {
+    nodes: Vec<TreeNode>,
+    free_list: Vec<usize>,
+    root: usize,
+    leaves: HashMap<Entity, usize>,
+}
+
+impl Default for DynamicTree {
+    fn default() -> Self {
+        DynamicTree {
+            nodes: vec!(),
+            free_list: vec!(),
+            root: NULL_NODE,
+            leaves: HashMap::new(),
+        }
+    }
+}
+
+fn union(min_a: Vec2, max_a: Vec2, min_b: Vec2, max_b: Vec2) -> (Vec2, Vec2) {
+    return (min_a.min(min_b), max_a.max(max_b));
+}
+
+fn perimeter(min: Vec2, max: Vec2) -> f32 {
+    let size = max - min;
+    return 2f32 * (size.x + size.y);
+}
+
+impl DynamicTree {
+    pub fn len(&self) -> usize {
+        return self.leaves.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.leaves.is_empty();
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        return self.leaves.contains_key(&entity);
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item=&Entity> {
+        return self.leaves.keys();
+    }
+
+    pub fn get_bounds(&self, entity: Entity) -> Option<(Vec2, Vec2)> {
+        let &leaf = self.leaves.get(&entity)?;
+        return Some((self.nodes[leaf].min, self.nodes[leaf].max));
+    }
+
+    /// Height of the tree, 0 if it only contains a single leaf
+    pub fn height(&self) -> i32 {
+        if self.root == NULL_NODE {
+            return 0;
+        }
+        return self.nodes[self.root].height;
+    }
+
+    pub fn clear(&mut self) {
+        *self = DynamicTree::default();
+    }
+
+    pub fn insert(&mut self, entity: Entity, min: Vec2, max: Vec2) {
+        if self.leaves.contains_key(&entity) {
+            self.update(entity, min, max);
+            return;
+        }
+
+        let leaf = self.allocate_node();
+        self.nodes[leaf].min = min;
+        self.nodes[leaf].max = max;
+        self.nodes[leaf].height = 0;
+        self.nodes[leaf].entity = Some(entity);
+
+        self.insert_leaf(leaf);
+        self.leaves.insert(entity, leaf);
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> bool {
+        let Some(leaf) = self.leaves.remove(&entity) else { return false; };
+
+        self.remove_leaf(leaf);
+        self.free_node(leaf);
+        return true;
+    }
+
+    /// Move the leaf of the entity if the new bounds are not contained in the stored ones.
+    /// Returns true if the leaf was reinserted.
+    pub fn update(&mut self, entity: Entity, min: Vec2, max: Vec2) -> bool {
+        let Some(&leaf) = self.leaves.get(&entity) else {
+            self.insert(entity, min, max);
+            return true;
+        };
+
+        let node = &self.nodes[leaf];
+        if node.min.x <= min.x && node.min.y <= min.y && node.max.x >= max.x && node.max.y >= max.y {
+            return false;
+        }
+
+        self.remove_leaf(leaf);
+        self.nodes[leaf].min = min;
+        self.nodes[leaf].max = max;
+        self.insert_leaf(leaf);
+        return true;
+    }
+
+    /// Remove every entity for which the predicate returns false
+    pub fn retain(&mut self, mut predicate: impl FnMut(Entity) -> bool) {
+        let to_remove = self.leaves.keys()
+            .filter(|&&entity| !predicate(entity))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for entity in to_remove {
+            self.remove(entity);
+        }
+    }
+
+    /// Entities whose stored bounds overlap the given box
+    pub fn query(&self, min: Vec2, max: Vec2) -> Vec<Entity> {
+        let mut result = vec!();
+        if self.root == NULL_NODE {
+            return result;
+        }
+
+        let mut stack = vec!(self.root);
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !check_bounds_overlap(node.min, node.max, min, max) {
+                continue;
+            }
+
+            if node.is_leaf() {
+                result.push(node.entity.unwrap());
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+
+        return result;
+    }
+
+    /// Entities whose stored bounds are crossed by the ray `origin + direction * t`, t in [0, max_toi]
+    pub fn ray_query(&self, origin: Vec2, direction: Vec2, max_toi: f32) -> Vec<Entity> {
+        let mut result = vec!();
+        if self.root == NULL_NODE {
+            return result;
+        }
+
+        let mut stack = vec!(self.root);
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if check_ray_overlap(origin, direction, max_toi, node.min, node.max).is_none() {
+                continue;
+            }
+
+            if node.is_leaf() {
+                result.push(node.entity.unwrap());
+            } else {
+                stack.push(node.left);
+                stack.push(node.right);
+            }
+        }
+
+        return result;
+    }
+
+    /// Every pair of leaves with overlapping bounds, found by traversing the tree against itself
+    pub fn compute_pairs(&self, parallel: bool) -> Vec<(Entity, Entity)> {
+        if self.root == NULL_NODE {
+            return vec!();
+        }
+
+        let depth = if parallel { 0 } else { PARALLEL_DEPTH };
+        return self.self_pairs(self.root, depth);
+    }
+
+    fn self_pairs(&self, index: usize, depth: usize) -> Vec<(Entity, Entity)> {
+        let node = &self.nodes[index];
+        if node.is_leaf() {
+            return vec!();
+        }
+
+        let mut pairs = vec!();
+        if depth < PARALLEL_DEPTH {
+            let ((mut left, mut right), mut cross) = rayon::join(
+                || rayon::join(
+                    || self.self_pairs(node.left, depth + 1),
+                    || self.self_pairs(node.right, depth + 1),
+                ),
+                || {
+                    let mut cross = vec!();
+                    self.cross_pairs(node.left, node.right, &mut cross);
+                    cross
+                },
+            );
+            pairs.append(&mut left);
+            pairs.append(&mut right);
+            pairs.append(&mut cross);
+        } else {
+            pairs.append(&mut self.self_pairs(node.left, depth + 1));
+            pairs.append(&mut self.self_pairs(node.right, depth + 1));
+            self.cross_pairs(node.left, node.right, &mut pairs);
+        }
+
+        return pairs;
+    }
+
+    fn cross_pairs(&self, a: usize, b: usize, pairs: &mut Vec<(Entity, Entity)>) {
+        let node_a = &self.nodes[a];
+        let node_b = &self.nodes[b];
+
+        if !check_bounds_overlap(node_a.min, node_a.max, node_b.min, node_b.max) {
+            return;
+        }
+
+        match (node_a.is_leaf(), node_b.is_leaf()) {
+            (true, true) => {
+                pairs.push((node_a.entity.unwrap(), node_b.entity.unwrap()));
+            }
+            (true, false) => {
+                self.cross_pairs(a, node_b.left, pairs);
+                self.cross_pairs(a, node_b.right, pairs);
+            }
+            (false, true) => {
+                self.cross_pairs(node_a.left, b, pairs);
+                self.cross_pairs(node_a.right, b, pairs);
+            }
+            (false, false) => {
+                // Descend into the biggest node first
+                if perimeter(node_a.min, node_a.max) > perimeter(node_b.min, node_b.max) {
+                    self.cross_pairs(node_a.left, b, pairs);
+                    self.cross_pairs(node_a.right, b, pairs);
+                } else {
+                    self.cross_pairs(a, node_b.left, pairs);
+                    self.cross_pairs(a, node_b.right, pairs);
+                }
+            }
+        }
+    }
+
+    fn allocate_node(&mut self) -> usize {
+        let node = TreeNode {
+            min: Vec2::ZERO,
+            max: Vec2::ZERO,
+            parent: NULL_NODE,
+            left: NULL_NODE,
+            right: NULL_NODE,
+            height: 0,
+            entity: None,
+        };
+
+        if let Some(index) = self.free_list.pop() {
+            self.nodes[index] = node;
+            return index;
+        }
+
+        self.nodes.push(node);
+        return self.nodes.len() - 1;
+    }
+
+    fn free_node(&mut self, index: usize) {
+        self.nodes[index].height = -1;
+        self.nodes[index].entity = None;
+        self.free_list.push(index);
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NULL_NODE {
+            self.root = leaf;
+            self.nodes[leaf].parent = NULL_NODE;
+            return;
+        }
+
+        // Find the best sibling using the surface area heuristic
+        let leaf_min = self.nodes[leaf].min;
+        let leaf_max = self.nodes[leaf].max;
+
+        let mut index = self.root;
+        while !self.nodes[index].is_leaf() {
+            let node = &self.nodes[index];
+
+            let area = perimeter(node.min, node.max);
+            let (combined_min, combined_max) = union(node.min, node.max, leaf_min, leaf_max);
+            let combined_area = perimeter(combined_min, combined_max);
+
+            // Cost of creating a new parent for this node and the new leaf
+            let cost = 2f32 * combined_area;
+            // Minimum cost of pushing the leaf further down the tree
+            let inheritance_cost = 2f32 * (combined_area - area);
+
+            let child_cost = |child: usize| {
+                let child = &self.nodes[child];
+                let (min, max) = union(child.min, child.max, leaf_min, leaf_max);
+                if child.is_leaf() {
+                    return perimeter(min, max) + inheritance_cost;
+                }
+                return perimeter(min, max) - perimeter(child.min, child.max) + inheritance_cost;
+            };
+
+            let cost_left = child_cost(node.left);
+            let cost_right = child_cost(node.right);
+
+            if cost < cost_left && cost < cost_right {
+                break;
+            }
+
+            index = if cost_left < cost_right { node.left } else { node.right };
+        }
+
+        let sibling = index;
+        let old_parent = self.nodes[sibling].parent;
+        let new_parent = self.allocate_node();
+
+        let (min, max) = union(self.nodes[sibling].min, self.nodes[sibling].max, leaf_min, leaf_max);
+        self.nodes[new_parent].parent = old_parent;
+        self.nodes[new_parent].min = min;
+        self.nodes[new_parent].max = max;
+        self.nodes[new_parent].height = self.nodes[sibling].height + 1;
+        self.nodes[new_parent].left = sibling;
+        self.nodes[new_parent].right = leaf;
+
+        if old_parent != NULL_NODE {
+            if self.nodes[old_parent].left == sibling {
+                self.nodes[old_parent].left = new_parent;
+            } else {
+                self.nodes[old_parent].right = new_parent;
+            }
+        } else {
+            self.root = new_parent;
+        }
+
+        self.nodes[sibling].parent = new_parent;
+        self.nodes[leaf].parent = new_parent;
+
+        self.refit_upwards(new_parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        if leaf == self.root {
+            self.root = NULL_NODE;
+            return;
+        }
+
+        let parent = self.nodes[leaf].parent;
+        let grand_parent = self.nodes[parent].parent;
+        let sibling = if self.nodes[parent].left == leaf { self.nodes[parent].right } else { self.nodes[parent].left };
+
+        if grand_parent != NULL_NODE {
+            if self.nodes[grand_parent].left == parent {
+                self.nodes[grand_parent].left = sibling;
+            } else {
+                self.nodes[grand_parent].right = sibling;
+            }
+            self.nodes[sibling].parent = grand_parent;
+            self.free_node(parent);
+
+            self.refit_upwards(grand_parent);
+        } else {
+            self.root = sibling;
+            self.nodes[sibling].parent = NULL_NODE;
+            self.free_node(parent);
+        }
+    }
+
+    // Walk back to the root, balancing the tree and fixing heights and bounds on the way
+    fn refit_upwards(&mut self, mut index: usize) {
+        while index != NULL_NODE {
+            index = self.balance(index);
+
+            let left = self.nodes[index].left;
+            let right = self.nodes[index].right;
+
+            let (min, max) = union(self.nodes[left].min, self.nodes[left].max, self.nodes[right].min, self.nodes[right].max);
+            self.nodes[index].height = 1 + self.nodes[left].height.max(self.nodes[right].height);
+            self.nodes[index].min = min;
+            self.nodes[index].max = max;
+
+            index = self.nodes[index].parent;
+        }
+    }
+
+    fn set_bounds_from_children(&mut self, index: usize, child_1: usize, child_2: usize) {
+        let (min, max) = union(self.nodes[child_1].min, self.nodes[child_1].max, self.nodes[child_2].min, self.nodes[child_2].max);
+        self.nodes[index].min = min;
+        self.nodes[index].max = max;
+        self.nodes[index].height = 1 + self.nodes[child_1].height.max(self.nodes[child_2].height);
+    }
+
+    // Perform a left or right rotation if node A is imbalanced, returns the new root of the subtree
+    fn balance(&mut self, a: usize) -> usize {
+        if self.nodes[a].is_leaf() || self.nodes[a].height < 2 {
+            return a;
+        }
+
+        let b = self.nodes[a].left;
+        let c = self.nodes[a].right;
+
+        let balance = self.nodes[c].height - self.nodes[b].height;
+
+        // Rotate C up
+        if balance > 1 {
+            let f = self.nodes[c].left;
+            let g = self.nodes[c].right;
+
+            self.nodes[c].left = a;
+            self.nodes[c].parent = self.nodes[a].parent;
+            self.nodes[a].parent = c;
+            self.replace_child(self.nodes[c].parent, a, c);
+
+            if self.nodes[f].height > self.nodes[g].height {
+                self.nodes[c].right = f;
+                self.nodes[a].right = g;
+                self.nodes[g].parent = a;
+                self.set_bounds_from_children(a, b, g);
+                self.set_bounds_from_children(c, a, f);
+            } else {
+                self.nodes[c].right = g;
+                self.nodes[a].right = f;
+                self.nodes[f].parent = a;
+                self.set_bounds_from_children(a, b, f);
+                self.set_bounds_from_children(c, a, g);
+            }
+
+            return c;
+        }
+
+        // Rotate B up
+        if balance < -1 {
+            let d = self.nodes[b].left;
+            let e = self.nodes[b].right;
+
+            self.nodes[b].left = a;
+            self.nodes[b].parent = self.nodes[a].parent;
+            self.nodes[a].parent = b;
+            self.replace_child(self.nodes[b].parent, a, b);
+
+            if self.nodes[d].height > self.nodes[e].height {
+                self.nodes[b].right = d;
+                self.nodes[a].left = e;
+                self.nodes[e].parent = a;
+                self.set_bounds_from_children(a, c, e);
+                self.set_bounds_from_children(b, a, d);
+            } else {
+                self.nodes[b].right = e;
+                self.nodes[a].left = d;
+                self.nodes[d].parent = a;
+                self.set_bounds_from_children(a, c, d);
+                self.set_bounds_from_children(b, a, e);
+            }
+
+            return b;
+        }
+
+        return a;
+    }
+
+    fn replace_child(&mut self, parent: usize, old_child: usize, new_child: usize) {
+        if parent == NULL_NODE {
+            self.root = new_child;
+        } else if self.nodes[parent].left == old_child {
+            self.nodes[parent].left = new_child;
+        } else {
+            self.nodes[parent].right = new_child;
+        }
+    }
+}
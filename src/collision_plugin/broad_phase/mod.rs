@@ -1 +1,8 @@
 pub mod systems;
+pub mod dynamic_tree;
+pub mod spatial_grid;
+pub mod sweep_and_prune;
+pub mod quadtree;
+
+#[cfg(test)]
+mod tests;
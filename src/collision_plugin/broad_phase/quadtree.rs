@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rayon::prelude::*;
+
+use crate::collision_plugin::aabb::{check_bounds_overlap, check_ray_overlap};
+
+const MAX_DEPTH: usize = 10;
+// Loose bounds of a node are its bounds scaled by this factor
+const LOOSENESS: f32 = 2f32;
+// The root is made this much bigger than the scene when it is rebuilt
+const ROOT_SLACK: f32 = 2f32;
+
+struct QuadNode {
+    center: Vec2,
+    half_size: f32,
+    depth: usize,
+    children: Option<[usize; 4]>,
+    items: Vec<usize>,
+}
+
+impl QuadNode {
+    fn loose_bounds(&self) -> (Vec2, Vec2) {
+        let half_size = Vec2::splat(self.half_size * LOOSENESS);
+        return (self.center - half_size, self.center + half_size);
+    }
+}
+
+struct QuadItem {
+    entity: Entity,
+    min: Vec2,
+    max: Vec2,
+    node: usize,
+}
+
+/// Loose quadtree. Each entity is stored in the deepest node whose loose bounds contain its AABB,
+/// so entities never need to be split across several nodes and small moves rarely change the node.
+pub struct LooseQuadtree {
+    nodes: Vec<QuadNode>,
+    items: Vec<QuadItem>,
+    free_items: Vec<usize>,
+    item_map: HashMap<Entity, usize>,
+}
+
+impl Default for LooseQuadtree {
+    fn default() -> Self {
+        LooseQuadtree::new(Vec2::ZERO, 1f32)
+    }
+}
+
+impl LooseQuadtree {
+    pub fn new(center: Vec2, half_size: f32) -> Self {
+        LooseQuadtree {
+            nodes: vec!(QuadNode {
+                center,
+                half_size,
+                depth: 0,
+                children: None,
+                items: vec!(),
+            }),
+            items: vec!(),
+            free_items: vec!(),
+            item_map: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        return self.item_map.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.item_map.is_empty();
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        return self.item_map.contains_key(&entity);
+    }
+
+    /// True if the box is inside the bounds of the root node
+    pub fn covers(&self, min: Vec2, max: Vec2) -> bool {
+        let root = &self.nodes[0];
+        let half_size = Vec2::splat(root.half_size);
+        return (min - (root.center - half_size)).min_element() >= 0f32
+            && ((root.center + half_size) - max).min_element() >= 0f32;
+    }
+
+    /// Recreate the tree around the given scene bounds, keeping every entity
+    pub fn rebuild(&mut self, scene_min: Vec2, scene_max: Vec2) {
+        let center = (scene_min + scene_max) * 0.5f32;
+        let half_size = ((scene_max - scene_min).max_element() * 0.5f32 * ROOT_SLACK).max(1f32);
+
+        let boxes = self.item_map.values()
+            .map(|&index| (self.items[index].entity, self.items[index].min, self.items[index].max))
+            .collect::<Vec<_>>();
+
+        *self = LooseQuadtree::new(center, half_size);
+        for (entity, min, max) in boxes {
+            self.insert(entity, min, max);
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity, min: Vec2, max: Vec2) {
+        if self.item_map.contains_key(&entity) {
+            self.update(entity, min, max);
+            return;
+        }
+
+        let node = self.find_node(min, max);
+        let item = QuadItem { entity, min, max, node };
+
+        let index = if let Some(index) = self.free_items.pop() {
+            self.items[index] = item;
+            index
+        } else {
+            self.items.push(item);
+            self.items.len() - 1
+        };
+
+        self.nodes[node].items.push(index);
+        self.item_map.insert(entity, index);
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> bool {
+        let Some(index) = self.item_map.remove(&entity) else { return false; };
+
+        let node = self.items[index].node;
+        self.nodes[node].items.retain(|&item| item != index);
+        self.free_items.push(index);
+        return true;
+    }
+
+    /// Update the box of an entity, it only moves to another node if it doesn't fit its current one anymore.
+    /// Returns true if the entity changed node.
+    pub fn update(&mut self, entity: Entity, min: Vec2, max: Vec2) -> bool {
+        let Some(&index) = self.item_map.get(&entity) else {
+            self.insert(entity, min, max);
+            return true;
+        };
+
+        self.items[index].min = min;
+        self.items[index].max = max;
+
+        let current = self.items[index].node;
+        let best = self.find_node(min, max);
+        if best == current {
+            return false;
+        }
+
+        // Still fits the loose bounds of its node, and no deeper node would take it
+        let (loose_min, loose_max) = self.nodes[current].loose_bounds();
+        let fits_current = (current == 0 || (min.cmpge(loose_min).all() && max.cmple(loose_max).all()))
+            && self.nodes[best].depth <= self.nodes[current].depth;
+        if fits_current {
+            return false;
+        }
+
+        self.nodes[current].items.retain(|&item| item != index);
+        self.nodes[best].items.push(index);
+        self.items[index].node = best;
+        return true;
+    }
+
+    /// Remove every entity for which the predicate returns false
+    pub fn retain(&mut self, mut predicate: impl FnMut(Entity) -> bool) {
+        let to_remove = self.item_map.keys()
+            .filter(|&&entity| !predicate(entity))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for entity in to_remove {
+            self.remove(entity);
+        }
+    }
+
+    /// Entities whose box overlaps the given region
+    pub fn query(&self, min: Vec2, max: Vec2) -> Vec<Entity> {
+        let mut result = vec!();
+        self.query_node(0, min, max, &mut result);
+        return result.into_iter().map(|index| self.items[index].entity).collect();
+    }
+
+    /// Entities whose box is crossed by the ray `origin + direction * t`, t in [0, max_toi]
+    pub fn ray_query(&self, origin: Vec2, direction: Vec2, max_toi: f32) -> Vec<Entity> {
+        let mut result = vec!();
+        self.ray_query_node(0, origin, direction, max_toi, &mut result);
+        return result.into_iter().map(|index| self.items[index].entity).collect();
+    }
+
+    /// Every pair of overlapping boxes. Loose bounds of neighbouring nodes overlap, so each entity
+    /// queries the tree from the root and only keeps the entities stored after it.
+    pub fn compute_pairs(&self, parallel: bool) -> Vec<(Entity, Entity)> {
+        let item_pairs = |&index: &usize| {
+            let item = &self.items[index];
+
+            let mut found = vec!();
+            self.query_node(0, item.min, item.max, &mut found);
+
+            return found.into_iter()
+                .filter(|&other| other > index)
+                .map(|other| (item.entity, self.items[other].entity))
+                .collect::<Vec<_>>();
+        };
+
+        let indices = self.item_map.values().cloned().collect::<Vec<_>>();
+        if parallel {
+            return indices.par_iter().flat_map_iter(item_pairs).collect();
+        }
+        return indices.iter().flat_map(item_pairs).collect();
+    }
+
+    // Deepest node in which the box fits, creating the nodes on the way
+    fn find_node(&mut self, min: Vec2, max: Vec2) -> usize {
+        let center = (min + max) * 0.5f32;
+        let extent = (max - min).max_element() * 0.5f32;
+
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            if node.depth >= MAX_DEPTH || extent > node.half_size * 0.5f32 {
+                return index;
+            }
+
+            // Outside of the root, keep it there
+            let offset = center - node.center;
+            if index == 0 && offset.abs().max_element() > node.half_size {
+                return index;
+            }
+
+            let quadrant = (offset.x >= 0f32) as usize + 2 * (offset.y >= 0f32) as usize;
+            index = self.get_or_create_children(index)[quadrant];
+        }
+    }
+
+    fn get_or_create_children(&mut self, index: usize) -> [usize; 4] {
+        if let Some(children) = self.nodes[index].children {
+            return children;
+        }
+
+        let (center, half_size, depth) = (self.nodes[index].center, self.nodes[index].half_size * 0.5f32, self.nodes[index].depth + 1);
+        let first = self.nodes.len();
+        for quadrant in 0..4 {
+            let direction = Vec2::new(
+                if quadrant & 1 == 1 { 1f32 } else { -1f32 },
+                if quadrant & 2 == 2 { 1f32 } else { -1f32 },
+            );
+            self.nodes.push(QuadNode {
+                center: center + direction * half_size,
+                half_size,
+                depth,
+                children: None,
+                items: vec!(),
+            });
+        }
+
+        let children = [first, first + 1, first + 2, first + 3];
+        self.nodes[index].children = Some(children);
+        return children;
+    }
+
+    fn query_node(&self, index: usize, min: Vec2, max: Vec2, result: &mut Vec<usize>) {
+        let node = &self.nodes[index];
+
+        // The root also holds the boxes outside of its bounds
+        let (loose_min, loose_max) = node.loose_bounds();
+        if index != 0 && !check_bounds_overlap(loose_min, loose_max, min, max) {
+            return;
+        }
+
+        for &item in &node.items {
+            let QuadItem { min: item_min, max: item_max, .. } = self.items[item];
+            if check_bounds_overlap(item_min, item_max, min, max) {
+                result.push(item);
+            }
+        }
+
+        if let Some(children) = node.children {
+            for child in children {
+                self.query_node(child, min, max, result);
+            }
+        }
+    }
+
+    fn ray_query_node(&self, index: usize, origin: Vec2, direction: Vec2, max_toi: f32, result: &mut Vec<usize>) {
+        let node = &self.nodes[index];
+
+        // The root also holds the boxes outside of its bounds
+        let (loose_min, loose_max) = node.loose_bounds();
+        if index != 0 && check_ray_overlap(origin, direction, max_toi, loose_min, loose_max).is_none() {
+            return;
+        }
+
+        for &item in &node.items {
+            let QuadItem { min: item_min, max: item_max, .. } = self.items[item];
+            if check_ray_overlap(origin, direction, max_toi, item_min, item_max).is_some() {
+                result.push(item);
+            }
+        }
+
+        if let Some(children) = node.children {
+            for child in children {
+                self.ray_query_node(child, origin, direction, max_toi, result);
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rayon::prelude::*;
+
+use crate::collision_plugin::aabb::check_bounds_overlap;
+
+// Cell size computed in auto mode, relative to the median AABB extent
+const AUTO_CELL_SIZE_FACTOR: f32 = 2f32;
+// Smallest cell size, relative to the median AABB extent, so the boxes don't each cover thousands of cells
+const MIN_CELL_SIZE_DIVISOR: f32 = 8f32;
+// Most cells the largest AABB can cover along an axis, which bounds the grid even when most boxes are degenerate
+const MAX_CELLS_PER_AXIS: f32 = 256f32;
+
+/// Uniform grid hashing every AABB into the fixed-size cells it overlaps
+pub struct SpatialGrid {
+    cell_size: f32,
+    boxes: Vec<(Entity, Vec2, Vec2)>,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SpatialGrid {
+    /// Build the grid from world space boxes. Without a `cell_size`, it is deduced from the median box size.
+    /// The cell size is raised when it is too small for the boxes, so they never cover too many cells.
+    pub fn new(boxes: Vec<(Entity, Vec2, Vec2)>, cell_size: Option<f32>) -> Self {
+        let cell_size = match cell_size {
+            Some(cell_size) => cell_size.max(Self::min_cell_size(&boxes)),
+            None => Self::auto_cell_size(&boxes),
+        };
+
+        let mut grid = SpatialGrid {
+            cell_size,
+            boxes,
+            cells: HashMap::new(),
+        };
+
+        for index in 0..grid.boxes.len() {
+            let (_, min, max) = grid.boxes[index];
+            let (min_cell, max_cell) = (grid.get_cell(min), grid.get_cell(max));
+            for x in min_cell.0..=max_cell.0 {
+                for y in min_cell.1..=max_cell.1 {
+                    grid.cells.entry((x, y)).or_default().push(index);
+                }
+            }
+        }
+
+        return grid;
+    }
+
+    pub fn auto_cell_size(boxes: &[(Entity, Vec2, Vec2)]) -> f32 {
+        if boxes.is_empty() {
+            return 1f32;
+        }
+
+        let (median, _) = Self::get_extents(boxes);
+        return (median * AUTO_CELL_SIZE_FACTOR).max(Self::min_cell_size(boxes));
+    }
+
+    /// Smallest cell size allowed for these boxes
+    pub fn min_cell_size(boxes: &[(Entity, Vec2, Vec2)]) -> f32 {
+        let (median, largest) = Self::get_extents(boxes);
+        return (median / MIN_CELL_SIZE_DIVISOR).max(largest / MAX_CELLS_PER_AXIS).max(f32::EPSILON);
+    }
+
+    // Median and largest extent of the boxes, along their longest axis
+    fn get_extents(boxes: &[(Entity, Vec2, Vec2)]) -> (f32, f32) {
+        if boxes.is_empty() {
+            return (0f32, 0f32);
+        }
+
+        let mut extents = boxes.iter()
+            .map(|(_, min, max)| (*max - *min).max_element())
+            .collect::<Vec<_>>();
+
+        let largest = extents.iter().cloned().fold(0f32, f32::max);
+        let middle = extents.len() / 2;
+        let (_, &mut median, _) = extents.select_nth_unstable_by(middle, |lhs, rhs| lhs.total_cmp(rhs));
+
+        return (median, largest);
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        return self.cell_size;
+    }
+
+    pub fn get_cell(&self, point: Vec2) -> (i32, i32) {
+        let cell = (point / self.cell_size).floor();
+        return (cell.x as i32, cell.y as i32);
+    }
+
+    /// Every pair of overlapping boxes, each pair being reported once
+    pub fn compute_pairs(&self, parallel: bool) -> Vec<(Entity, Entity)> {
+        if parallel {
+            return self.cells.par_iter()
+                .flat_map_iter(|(&cell, indices)| self.cell_pairs(cell, indices))
+                .collect();
+        }
+
+        return self.cells.iter()
+            .flat_map(|(&cell, indices)| self.cell_pairs(cell, indices))
+            .collect();
+    }
+
+    /// Entities whose box overlaps the given box
+    pub fn query(&self, min: Vec2, max: Vec2) -> Vec<Entity> {
+        let (min_cell, max_cell) = (self.get_cell(min), self.get_cell(max));
+
+        let mut result = vec!();
+        for x in min_cell.0..=max_cell.0 {
+            for y in min_cell.1..=max_cell.1 {
+                let Some(indices) = self.cells.get(&(x, y)) else { continue; };
+                for &index in indices {
+                    let (entity, box_min, box_max) = self.boxes[index];
+                    // Only report the box in the first visited cell it overlaps
+                    let first_cell = self.get_cell(box_min.max(min));
+                    if first_cell == (x, y) && check_bounds_overlap(box_min, box_max, min, max) {
+                        result.push(entity);
+                    }
+                }
+            }
+        }
+
+        return result;
+    }
+
+    fn cell_pairs(&self, cell: (i32, i32), indices: &[usize]) -> Vec<(Entity, Entity)> {
+        let mut pairs = vec!();
+        for (i, &index_a) in indices.iter().enumerate() {
+            let (entity_a, min_a, max_a) = self.boxes[index_a];
+            for &index_b in &indices[i + 1..] {
+                let (entity_b, min_b, max_b) = self.boxes[index_b];
+                if !check_bounds_overlap(min_a, max_a, min_b, max_b) {
+                    continue;
+                }
+
+                // Two boxes can share several cells, only emit the pair in the first shared one
+                if self.get_cell(min_a.max(min_b)) == cell {
+                    pairs.push((entity_a, entity_b));
+                }
+            }
+        }
+        return pairs;
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use rayon::prelude::*;
+
+use crate::collision_plugin::aabb::check_bounds_overlap;
+use crate::collision_plugin::collision_layers::CollisionLayers;
+use crate::collision_plugin::data_structs::CollisionPair;
+use crate::collision_plugin::rigidbody::BodyType;
+
+// The sweep axis is switched when the variance on the other axis is this much bigger
+const AXIS_SWITCH_RATIO: f32 = 1.5f32;
+
+struct Proxy {
+    entity: Entity,
+    min: Vec2,
+    max: Vec2,
+    layers: CollisionLayers,
+    body_type: BodyType,
+    // Proxies overlapping this one on the sweep axis
+    partners: HashSet<usize>,
+    seen: bool,
+    alive: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Endpoint {
+    value: f32,
+    proxy: usize,
+    is_max: bool,
+}
+
+impl Endpoint {
+    // Min endpoints go first on equal values, so touching boxes are overlapping like in check_collision
+    fn is_after(&self, other: &Endpoint) -> bool {
+        return self.value > other.value || (self.value == other.value && self.is_max && !other.is_max);
+    }
+}
+
+fn pair_key(a: usize, b: usize) -> (usize, usize) {
+    return if a < b { (a, b) } else { (b, a) };
+}
+
+/// Incremental sweep and prune. Endpoints of every box on the sweep axis are kept sorted between
+/// steps with an insertion sort, and the swaps tell which boxes start or stop overlapping on that axis.
+/// Colliding pairs are only re-evaluated for boxes that moved, and are applied as deltas to the output.
+pub struct SweepAndPrune {
+    axis: usize,
+    proxies: Vec<Proxy>,
+    free_proxies: Vec<usize>,
+    proxy_map: HashMap<Entity, usize>,
+    endpoints: Vec<Endpoint>,
+    colliding: HashSet<(usize, usize)>,
+
+    // Position of each colliding pair in the output vector, and the reverse mapping
+    pair_slots: HashMap<(usize, usize), usize>,
+    slot_keys: Vec<(usize, usize)>,
+    output_in_sync: bool,
+    recheck_all: bool,
+
+    added_pairs: Vec<CollisionPair>,
+    added_keys: Vec<(usize, usize)>,
+    removed_keys: Vec<(usize, usize)>,
+}
+
+impl Default for SweepAndPrune {
+    fn default() -> Self {
+        SweepAndPrune {
+            axis: 0,
+            proxies: vec!(),
+            free_proxies: vec!(),
+            proxy_map: HashMap::new(),
+            endpoints: vec!(),
+            colliding: HashSet::new(),
+            pair_slots: HashMap::new(),
+            slot_keys: vec!(),
+            output_in_sync: false,
+            recheck_all: false,
+            added_pairs: vec!(),
+            added_keys: vec!(),
+            removed_keys: vec!(),
+        }
+    }
+}
+
+impl SweepAndPrune {
+    /// Current sweep axis, 0 for X and 1 for Y
+    pub fn axis(&self) -> usize {
+        return self.axis;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.proxy_map.len();
+    }
+
+    /// Number of pairs written at the front of the output vector by the last update
+    pub fn pair_count(&self) -> usize {
+        return self.slot_keys.len();
+    }
+
+    /// Must be called when the output vector was modified by someone else,
+    /// it will then be rebuilt entirely on the next update
+    pub fn invalidate_output(&mut self) {
+        self.output_in_sync = false;
+    }
+
+    /// Must be called when `can_collide` changed its answer for some pairs,
+    /// every overlapping pair will be evaluated again on the next update
+    pub fn recheck_pairs(&mut self) {
+        self.recheck_all = true;
+    }
+
+    /// Entities whose box overlaps the given region, found from the sorted endpoints of the sweep axis
+    pub fn query(&self, min: Vec2, max: Vec2) -> Vec<Entity> {
+        // Boxes starting after the region on the sweep axis can't overlap it
+        let end = self.endpoints.partition_point(|endpoint| endpoint.value <= max[self.axis]);
+
+        return self.endpoints[..end].iter()
+            .filter(|endpoint| !endpoint.is_max)
+            .map(|endpoint| &self.proxies[endpoint.proxy])
+            .filter(|proxy| proxy.alive && check_bounds_overlap(proxy.min, proxy.max, min, max))
+            .map(|proxy| proxy.entity)
+            .collect();
+    }
+
+    /// Synchronize with the given world space boxes and apply the changes to `collision_pairs`.
+    /// Entities that are not part of `boxes` anymore are removed. Pairs with non interacting layers,
+    /// or refused by `can_collide`, are never reported. The pairs of an entity whose layers or body type
+    /// changed are evaluated again.
+    pub fn update(&mut self,
+                  boxes: &[(Entity, Vec2, Vec2, CollisionLayers, BodyType)],
+                  collision_pairs: &mut Vec<CollisionPair>,
+                  parallel: bool,
+                  can_collide: &(dyn Fn(Entity, Entity) -> bool + Sync),
+    ) {
+        self.added_pairs.clear();
+        self.added_keys.clear();
+        self.removed_keys.clear();
+
+        let mut dirty = self.sync_proxies(boxes);
+        self.remove_unseen_proxies();
+
+        let mut to_check = HashSet::new();
+        if self.select_axis() {
+            self.rebuild_endpoints();
+            dirty = self.proxy_map.values().cloned().collect();
+            to_check.extend(self.colliding.iter().cloned());
+        } else {
+            self.refresh_endpoints();
+            self.insertion_sort(&mut to_check);
+        }
+
+        if self.recheck_all {
+            dirty = self.proxy_map.values().cloned().collect();
+            self.recheck_all = false;
+        }
+
+        for &proxy in &dirty {
+            for &partner in &self.proxies[proxy].partners {
+                to_check.insert(pair_key(proxy, partner));
+            }
+        }
+
+        self.evaluate_pairs(to_check, parallel, can_collide);
+        self.apply_output(collision_pairs);
+    }
+
+    // Update the bounds of known entities and create proxies for the new ones, returns the proxies that changed
+    fn sync_proxies(&mut self, boxes: &[(Entity, Vec2, Vec2, CollisionLayers, BodyType)]) -> Vec<usize> {
+        for proxy in self.proxies.iter_mut() {
+            proxy.seen = false;
+        }
+
+        let mut dirty = vec!();
+        for &(entity, min, max, layers, body_type) in boxes {
+            if let Some(&index) = self.proxy_map.get(&entity) {
+                let proxy = &mut self.proxies[index];
+                proxy.seen = true;
+                if proxy.min != min || proxy.max != max || proxy.layers != layers || proxy.body_type != body_type {
+                    proxy.min = min;
+                    proxy.max = max;
+                    proxy.layers = layers;
+                    proxy.body_type = body_type;
+                    dirty.push(index);
+                }
+                continue;
+            }
+
+            let proxy = Proxy {
+                entity,
+                min,
+                max,
+                layers,
+                body_type,
+                partners: HashSet::new(),
+                seen: true,
+                alive: true,
+            };
+
+            let index = if let Some(index) = self.free_proxies.pop() {
+                self.proxies[index] = proxy;
+                index
+            } else {
+                self.proxies.push(proxy);
+                self.proxies.len() - 1
+            };
+
+            self.proxy_map.insert(entity, index);
+            // New endpoints are appended, the insertion sort moves them to their place
+            self.endpoints.push(Endpoint { value: min[self.axis], proxy: index, is_max: false });
+            self.endpoints.push(Endpoint { value: max[self.axis], proxy: index, is_max: true });
+            dirty.push(index);
+        }
+
+        return dirty;
+    }
+
+    fn remove_unseen_proxies(&mut self) {
+        let unseen = self.proxies.iter()
+            .enumerate()
+            .filter(|(_, proxy)| proxy.alive && !proxy.seen)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        if unseen.is_empty() {
+            return;
+        }
+
+        for &index in &unseen {
+            let partners = std::mem::take(&mut self.proxies[index].partners);
+            for partner in partners {
+                self.proxies[partner].partners.remove(&index);
+                self.set_colliding(index, partner, false);
+            }
+
+            let proxy = &mut self.proxies[index];
+            proxy.alive = false;
+            self.proxy_map.remove(&proxy.entity);
+            self.free_proxies.push(index);
+        }
+
+        let proxies = &self.proxies;
+        self.endpoints.retain(|endpoint| proxies[endpoint.proxy].alive);
+    }
+
+    // Switch the sweep axis if the boxes are much more spread along the other one, returns true if it changed
+    fn select_axis(&mut self) -> bool {
+        let count = self.proxy_map.len() as f32;
+        if count < 2f32 {
+            return false;
+        }
+
+        let (sum, sum_squared) = self.proxy_map.values()
+            .map(|&index| (self.proxies[index].min + self.proxies[index].max) * 0.5f32)
+            .fold((Vec2::ZERO, Vec2::ZERO), |(sum, sum_squared), center| (sum + center, sum_squared + center * center));
+
+        let mean = sum / count;
+        let variance = sum_squared / count - mean * mean;
+
+        let other_axis = 1 - self.axis;
+        if variance[other_axis] > variance[self.axis] * AXIS_SWITCH_RATIO {
+            self.axis = other_axis;
+            return true;
+        }
+        return false;
+    }
+
+    // Full sort and sweep, used when the axis changes
+    fn rebuild_endpoints(&mut self) {
+        self.refresh_endpoints();
+        self.endpoints.sort_by(|lhs, rhs| {
+            lhs.value.total_cmp(&rhs.value).then(lhs.is_max.cmp(&rhs.is_max))
+        });
+
+        for proxy in self.proxies.iter_mut() {
+            proxy.partners.clear();
+        }
+
+        let mut active = Vec::<usize>::new();
+        for i in 0..self.endpoints.len() {
+            let endpoint = self.endpoints[i];
+            if endpoint.is_max {
+                active.retain(|&proxy| proxy != endpoint.proxy);
+                continue;
+            }
+
+            for &other in &active {
+                self.proxies[other].partners.insert(endpoint.proxy);
+                self.proxies[endpoint.proxy].partners.insert(other);
+            }
+            active.push(endpoint.proxy);
+        }
+    }
+
+    fn refresh_endpoints(&mut self) {
+        let axis = self.axis;
+        for endpoint in self.endpoints.iter_mut() {
+            let proxy = &self.proxies[endpoint.proxy];
+            endpoint.value = if endpoint.is_max { proxy.max[axis] } else { proxy.min[axis] };
+        }
+    }
+
+    // Objects move little between steps, so the endpoints are nearly sorted and this is close to linear
+    fn insertion_sort(&mut self, to_check: &mut HashSet<(usize, usize)>) {
+        for i in 1..self.endpoints.len() {
+            let current = self.endpoints[i];
+            let mut j = i;
+            while j > 0 && self.endpoints[j - 1].is_after(&current) {
+                let previous = self.endpoints[j - 1];
+                if previous.proxy != current.proxy {
+                    self.on_swap(current, previous, to_check);
+                }
+                self.endpoints[j] = previous;
+                j -= 1;
+            }
+            self.endpoints[j] = current;
+        }
+    }
+
+    // `current` moved before `previous`. A min going before a max may start an overlap, a max going before a min may end it.
+    // The real overlap is checked with the final bounds, so the result doesn't depend on the order of the swaps.
+    fn on_swap(&mut self, current: Endpoint, previous: Endpoint, to_check: &mut HashSet<(usize, usize)>) {
+        if current.is_max == previous.is_max {
+            return;
+        }
+
+        let (a, b) = (current.proxy, previous.proxy);
+        let axis = self.axis;
+        let overlapping = self.proxies[a].min[axis] <= self.proxies[b].max[axis]
+            && self.proxies[a].max[axis] >= self.proxies[b].min[axis];
+
+        if overlapping {
+            self.proxies[a].partners.insert(b);
+            self.proxies[b].partners.insert(a);
+            to_check.insert(pair_key(a, b));
+        } else {
+            self.proxies[a].partners.remove(&b);
+            self.proxies[b].partners.remove(&a);
+            self.set_colliding(a, b, false);
+        }
+    }
+
+    fn evaluate_pairs(&mut self, to_check: HashSet<(usize, usize)>, parallel: bool, can_collide: &(dyn Fn(Entity, Entity) -> bool + Sync)) {
+        let proxies = &self.proxies;
+        let overlap = |&(a, b): &(usize, usize)| {
+            let (proxy_a, proxy_b) = (&proxies[a], &proxies[b]);
+            let colliding = proxy_a.partners.contains(&b)
+                && proxy_a.layers.interacts_with(&proxy_b.layers)
+                && check_bounds_overlap(proxy_a.min, proxy_a.max, proxy_b.min, proxy_b.max)
+                && can_collide(proxy_a.entity, proxy_b.entity);
+            return (a, b, colliding);
+        };
+
+        let results = if parallel {
+            to_check.par_iter().map(overlap).collect::<Vec<_>>()
+        } else {
+            to_check.iter().map(overlap).collect::<Vec<_>>()
+        };
+
+        for (a, b, colliding) in results {
+            self.set_colliding(a, b, colliding);
+        }
+    }
+
+    fn set_colliding(&mut self, a: usize, b: usize, colliding: bool) {
+        let key = pair_key(a, b);
+        let pair = CollisionPair {
+            entity_a: self.proxies[key.0].entity,
+            entity_b: self.proxies[key.1].entity,
+        };
+
+        if colliding && self.colliding.insert(key) {
+            self.added_pairs.push(pair);
+            self.added_keys.push(key);
+        } else if !colliding && self.colliding.remove(&key) {
+            self.removed_keys.push(key);
+        }
+    }
+
+    fn apply_output(&mut self, collision_pairs: &mut Vec<CollisionPair>) {
+        if !self.output_in_sync || collision_pairs.len() != self.slot_keys.len() {
+            collision_pairs.clear();
+            self.slot_keys.clear();
+            self.pair_slots.clear();
+            for &key in &self.colliding {
+                self.pair_slots.insert(key, collision_pairs.len());
+                self.slot_keys.push(key);
+                collision_pairs.push(CollisionPair {
+                    entity_a: self.proxies[key.0].entity,
+                    entity_b: self.proxies[key.1].entity,
+                });
+            }
+            self.output_in_sync = true;
+            return;
+        }
+
+        for key in &self.removed_keys {
+            let Some(slot) = self.pair_slots.remove(key) else { continue; };
+
+            collision_pairs.swap_remove(slot);
+            self.slot_keys.swap_remove(slot);
+            if slot < self.slot_keys.len() {
+                self.pair_slots.insert(self.slot_keys[slot], slot);
+            }
+        }
+
+        // A pair can be removed then added back during the same update
+        for (&key, pair) in self.added_keys.iter().zip(&self.added_pairs) {
+            if self.pair_slots.contains_key(&key) || !self.colliding.contains(&key) {
+                continue;
+            }
+            self.pair_slots.insert(key, collision_pairs.len());
+            self.slot_keys.push(key);
+            collision_pairs.push(*pair);
+        }
+    }
+}
@@ -5,13 +5,19 @@ use rayon::prelude::*;
 
 use crate::{
     collision_plugin::{
-        aabb::{AABB, check_collision},
+        aabb::AABB,
+        collision_layers::{check_layers, CollisionLayers, PairExclusions},
+        ColliderFilter,
         data_structs::CollisionPair,
         PhysicsAwake,
-        polygon_component::PolygonComponent,
+        rigidbody::{BodyType, RigidBody2d},
     }
 };
 use crate::collision_plugin::config::{BroadPhaseType, CollisionConfig};
+use crate::collision_plugin::broad_phase::dynamic_tree::DynamicTree;
+use crate::collision_plugin::broad_phase::quadtree::LooseQuadtree;
+use crate::collision_plugin::broad_phase::spatial_grid::SpatialGrid;
+use crate::collision_plugin::broad_phase::sweep_and_prune::SweepAndPrune;
 use crate::collision_plugin::data_structs::BroadPhaseData;
 use crate::transform2d::Transform2d;
 
@@ -19,43 +25,95 @@ pub type BroadPhaseQueryAwake<'w, 's> = Query<
     'w, 's,
     (
         Entity,
-        &'static PolygonComponent,
         &'static Transform2d,
-        &'static AABB
+        &'static AABB,
+        Option<&'static CollisionLayers>,
+        Option<&'static RigidBody2d>,
     ),
-    With<PhysicsAwake>
+    (With<PhysicsAwake>, ColliderFilter)
 >;
 
 
 pub(crate) fn broad_phase(
     query: BroadPhaseQueryAwake,
     mut broad_phase_data: ResMut<BroadPhaseData>,
+    exclusions: Res<PairExclusions>,
     config: ResMut<CollisionConfig>)
 {
     let _span = info_span!("broad_phase", name = "dispatching").entered();
 
     let start = Instant::now();
-    broad_phase_data.collision_pairs.clear();
+
+    // The SAP keeps its pairs at the front of collision_pairs and only applies the pairs that changed,
+    // everything after them is computed again
+    if matches!(config.phase_config.broad_phase_type, BroadPhaseType::SAP) {
+        let sap_pair_count = broad_phase_data.sweep_and_prune.pair_count();
+        broad_phase_data.collision_pairs.truncate(sap_pair_count);
+    } else {
+        broad_phase_data.collision_pairs.clear();
+        broad_phase_data.sweep_and_prune.invalidate_output();
+    }
+
+    // Persistent pairs have to be checked again against the new exclusions
+    if exclusions.is_changed() {
+        broad_phase_data.sweep_and_prune.recheck_pairs();
+    }
 
     if config.phase_config.multithread_broad_phase {
         match config.phase_config.broad_phase_type {
             BroadPhaseType::Disabled => {}
             BroadPhaseType::Rough => {
-                broad_phase_data.collision_pairs = compute_collision_pairs_rough_mt(&query, &broad_phase_data.sorted_entities);
+                broad_phase_data.collision_pairs = compute_collision_pairs_rough_mt(&query, &exclusions, &broad_phase_data.awake_entities);
             }
             BroadPhaseType::SAP => {
-                broad_phase_data.collision_pairs = compute_collision_pairs_sap_mt(&query, &broad_phase_data.sorted_entities);
+                let BroadPhaseData { collision_pairs, sweep_and_prune, .. } = &mut *broad_phase_data;
+                update_collision_pairs_sap_mt(&query, &exclusions, sweep_and_prune, collision_pairs);
             }
+            BroadPhaseType::DynamicTree => {
+                update_dynamic_tree(&query, &mut broad_phase_data.dynamic_tree);
+                broad_phase_data.collision_pairs = compute_collision_pairs_dynamic_tree_mt(&query, &exclusions, &broad_phase_data.dynamic_tree);
+            }
+            BroadPhaseType::Grid => {
+                broad_phase_data.collision_pairs = compute_collision_pairs_grid_mt(&query, &exclusions, config.phase_config.get_grid_cell_size());
+            }
+            BroadPhaseType::Quadtree => {
+                update_quadtree(&query, &mut broad_phase_data.quadtree);
+                broad_phase_data.collision_pairs = compute_collision_pairs_quadtree_mt(&query, &exclusions, &broad_phase_data.quadtree);
+            }
+        }
+
+        if !matches!(config.phase_config.broad_phase_type, BroadPhaseType::Disabled) {
+            update_static_tree(&query, &mut broad_phase_data.static_tree);
+            let mut static_pairs = compute_collision_pairs_static_mt(&query, &exclusions, &broad_phase_data.static_tree);
+            broad_phase_data.collision_pairs.append(&mut static_pairs);
         }
     } else {
         match config.phase_config.broad_phase_type {
             BroadPhaseType::Disabled => {}
             BroadPhaseType::Rough => {
-                broad_phase_data.collision_pairs = compute_collision_pairs_rough_st(&query, &broad_phase_data.sorted_entities);
+                broad_phase_data.collision_pairs = compute_collision_pairs_rough_st(&query, &exclusions, &broad_phase_data.awake_entities);
             }
             BroadPhaseType::SAP => {
-                broad_phase_data.collision_pairs = compute_collision_pairs_sap_st(&query, &broad_phase_data.sorted_entities);
+                let BroadPhaseData { collision_pairs, sweep_and_prune, .. } = &mut *broad_phase_data;
+                update_collision_pairs_sap_st(&query, &exclusions, sweep_and_prune, collision_pairs);
             }
+            BroadPhaseType::DynamicTree => {
+                update_dynamic_tree(&query, &mut broad_phase_data.dynamic_tree);
+                broad_phase_data.collision_pairs = compute_collision_pairs_dynamic_tree_st(&query, &exclusions, &broad_phase_data.dynamic_tree);
+            }
+            BroadPhaseType::Grid => {
+                broad_phase_data.collision_pairs = compute_collision_pairs_grid_st(&query, &exclusions, config.phase_config.get_grid_cell_size());
+            }
+            BroadPhaseType::Quadtree => {
+                update_quadtree(&query, &mut broad_phase_data.quadtree);
+                broad_phase_data.collision_pairs = compute_collision_pairs_quadtree_st(&query, &exclusions, &broad_phase_data.quadtree);
+            }
+        }
+
+        if !matches!(config.phase_config.broad_phase_type, BroadPhaseType::Disabled) {
+            update_static_tree(&query, &mut broad_phase_data.static_tree);
+            let mut static_pairs = compute_collision_pairs_static_st(&query, &exclusions, &broad_phase_data.static_tree);
+            broad_phase_data.collision_pairs.append(&mut static_pairs);
         }
     }
 
@@ -65,17 +123,22 @@ pub(crate) fn broad_phase(
 }
 
 
-//Naively add every pairs to check
+//Naively add every pairs of moving entities to check
 #[must_use]
 pub(crate) fn compute_collision_pairs_rough_mt(
     query: &BroadPhaseQueryAwake,
+    exclusions: &PairExclusions,
     entities: &Vec<Entity>,
 ) -> Vec<CollisionPair>
 {
     let _span = info_span!("broad_phase", name = "compute rough collisions").entered();
 
     let mut collision_pairs = vec!();
-    collision_pairs.par_extend(query.iter_combinations().collect::<Vec<_>>().par_iter().filter_map(|[(e1, _p1, t1, _a1), (e2, _p2, t2, _a2)]| {
+    collision_pairs.par_extend(query.iter_combinations().collect::<Vec<_>>().par_iter().filter_map(|[(e1, _t1, _a1, _l1, rb1), (e2, _t2, _a2, _l2, rb2)]| {
+        // Static entities are handled by the static tree
+        if is_static(*rb1) || is_static(*rb2) || !check_pair_filter(query, exclusions, *e1, *e2) {
+            return None;
+        }
         return Some(CollisionPair {
             entity_a: *e1,
             entity_b: *e2,
@@ -85,17 +148,22 @@ pub(crate) fn compute_collision_pairs_rough_mt(
     return collision_pairs;
 }
 
-//Naively add every pairs to check
+//Naively add every pairs of moving entities to check
 #[must_use]
 pub(crate) fn compute_collision_pairs_rough_st(
     query: &BroadPhaseQueryAwake,
+    exclusions: &PairExclusions,
     entities: &Vec<Entity>,
 ) -> Vec<CollisionPair>
 {
     let _span = info_span!("broad_phase", name = "compute rough collisions").entered();
 
     let mut collision_pairs = vec!();
-    collision_pairs.extend(query.iter_combinations().collect::<Vec<_>>().iter().filter_map(|[(e1, _p1, t1, _a1), (e2, _p2, t2, _a2)]| {
+    collision_pairs.extend(query.iter_combinations().collect::<Vec<_>>().iter().filter_map(|[(e1, _t1, _a1, _l1, rb1), (e2, _t2, _a2, _l2, rb2)]| {
+        // Static entities are handled by the static tree
+        if is_static(*rb1) || is_static(*rb2) || !check_pair_filter(query, exclusions, *e1, *e2) {
+            return None;
+        }
         return Some(CollisionPair {
             entity_a: *e1,
             entity_b: *e2,
@@ -105,78 +173,335 @@ pub(crate) fn compute_collision_pairs_rough_st(
     return collision_pairs;
 }
 
-//Use Sweep & Prune to create a vector of CollisionPair
+//Use the persistent Sweep & Prune to update the vector of CollisionPair with the pairs that changed
+pub(crate) fn update_collision_pairs_sap_mt(
+    query: &BroadPhaseQueryAwake,
+    exclusions: &PairExclusions,
+    sweep_and_prune: &mut SweepAndPrune,
+    collision_pairs: &mut Vec<CollisionPair>,
+)
+{
+    let _span = info_span!("broad_phase", name = "compute SAP collisions").entered();
+
+    let boxes = query.iter()
+        .filter(|(_e, _t, _a, _l, rb)| !is_static(*rb))
+        .map(|(e, _t, a, l, rb)| (e, a.fat_min, a.fat_max, l.cloned().unwrap_or_default(), get_body_type(rb)))
+        .collect::<Vec<_>>();
+
+    sweep_and_prune.update(&boxes, collision_pairs, true, &|entity_a, entity_b| check_pair_filter(query, exclusions, entity_a, entity_b));
+}
+
+//Use the persistent Sweep & Prune to update the vector of CollisionPair with the pairs that changed
+pub(crate) fn update_collision_pairs_sap_st(
+    query: &BroadPhaseQueryAwake,
+    exclusions: &PairExclusions,
+    sweep_and_prune: &mut SweepAndPrune,
+    collision_pairs: &mut Vec<CollisionPair>,
+)
+{
+    let _span = info_span!("broad_phase", name = "compute SAP collisions").entered();
+
+    let boxes = query.iter()
+        .filter(|(_e, _t, _a, _l, rb)| !is_static(*rb))
+        .map(|(e, _t, a, l, rb)| (e, a.fat_min, a.fat_max, l.cloned().unwrap_or_default(), get_body_type(rb)))
+        .collect::<Vec<_>>();
+
+    sweep_and_prune.update(&boxes, collision_pairs, false, &|entity_a, entity_b| check_pair_filter(query, exclusions, entity_a, entity_b));
+}
+
+// Insert new entities, move the ones that left their leaf and remove the ones no longer in the query
+pub(crate) fn update_dynamic_tree(
+    query: &BroadPhaseQueryAwake,
+    tree: &mut DynamicTree,
+)
+{
+    let _span = info_span!("broad_phase", name = "update dynamic tree").entered();
+
+    tree.retain(|entity| is_moving_entity(query, entity));
+
+    for (e, _t, a, _l, _rb) in query.iter().filter(|(_e, _t, _a, _l, rb)| !is_static(*rb)) {
+        tree.update(e, a.fat_min, a.fat_max);
+    }
+}
+
+//Traverse the dynamic tree against itself to create a vector of CollisionPair
+#[must_use]
+pub(crate) fn compute_collision_pairs_dynamic_tree_mt(
+    query: &BroadPhaseQueryAwake,
+    exclusions: &PairExclusions,
+    tree: &DynamicTree,
+) -> Vec<CollisionPair>
+{
+    let _span = info_span!("broad_phase", name = "compute dynamic tree collisions").entered();
+
+    // Leaves hold the fat AABBs, so the overlapping leaves are the pairs
+    let collision_pairs = tree.compute_pairs(true).par_iter().filter_map(|&(entity_a, entity_b)| {
+        if !check_pair_filter(query, exclusions, entity_a, entity_b) {
+            return None;
+        }
+        return Some(CollisionPair {
+            entity_a,
+            entity_b,
+        });
+    }).collect::<Vec<_>>();
+
+    return collision_pairs;
+}
+
+//Traverse the dynamic tree against itself to create a vector of CollisionPair
 #[must_use]
-pub(crate) fn compute_collision_pairs_sap_mt(
+pub(crate) fn compute_collision_pairs_dynamic_tree_st(
     query: &BroadPhaseQueryAwake,
-    entities: &Vec<Entity>,
+    exclusions: &PairExclusions,
+    tree: &DynamicTree,
 ) -> Vec<CollisionPair>
 {
-    let _span = info_span!("broad_phase", name = "compute SAP collisions").entered();
+    let _span = info_span!("broad_phase", name = "compute dynamic tree collisions").entered();
 
-    let count = entities.len();
+    // Leaves hold the fat AABBs, so the overlapping leaves are the pairs
+    let collision_pairs = tree.compute_pairs(false).iter().filter_map(|&(entity_a, entity_b)| {
+        if !check_pair_filter(query, exclusions, entity_a, entity_b) {
+            return None;
+        }
+        return Some(CollisionPair {
+            entity_a,
+            entity_b,
+        });
+    }).collect::<Vec<_>>();
 
-    //fixme
-    let mut collision_pairs = vec!();
-    collision_pairs.par_extend(entities.par_iter().enumerate().flat_map(|(i, &entity1)|
-        {
-            let (e1, _p1, t1, a1) = query.get(entity1).unwrap();
-
-            let mut tmp_collision = Vec::<CollisionPair>::with_capacity(count - i - 1);
-            for &entity2 in entities.iter().skip(i + 1)
-            {
-                let (e2, _p2, t2, a2) = query.get(entity2).unwrap();
-                if t2.translation.x + a2.min.x > t1.translation.x + a1.max.x { break; };
-
-                if check_collision(&a1, &t1, &a2, &t2)
-                {
-                    tmp_collision.push(CollisionPair {
-                        entity_a: e1,
-                        entity_b: e2,
-                    });
-                }
-            }
-            return tmp_collision;
-        })
-        .collect::<Vec<_>>());
+    return collision_pairs;
+}
+
+//Hash every AABB in a uniform grid and test the entities sharing a cell
+#[must_use]
+pub(crate) fn compute_collision_pairs_grid_mt(
+    query: &BroadPhaseQueryAwake,
+    exclusions: &PairExclusions,
+    cell_size: Option<f32>,
+) -> Vec<CollisionPair>
+{
+    let _span = info_span!("broad_phase", name = "compute grid collisions").entered();
+
+    let boxes = query.iter()
+        .filter(|(_e, _t, _a, _l, rb)| !is_static(*rb))
+        .map(|(e, _t, a, _l, _rb)| (e, a.fat_min, a.fat_max))
+        .collect::<Vec<_>>();
+    let grid = SpatialGrid::new(boxes, cell_size);
+
+    let collision_pairs = grid.compute_pairs(true).par_iter().filter_map(|&(entity_a, entity_b)| {
+        if !check_pair_filter(query, exclusions, entity_a, entity_b) {
+            return None;
+        }
+        return Some(CollisionPair {
+            entity_a,
+            entity_b,
+        });
+    }).collect::<Vec<_>>();
 
     return collision_pairs;
 }
 
-//Use Sweep & Prune to create a vector of CollisionPair
+//Hash every AABB in a uniform grid and test the entities sharing a cell
 #[must_use]
-pub(crate) fn compute_collision_pairs_sap_st(
+pub(crate) fn compute_collision_pairs_grid_st(
     query: &BroadPhaseQueryAwake,
-    entities: &Vec<Entity>,
+    exclusions: &PairExclusions,
+    cell_size: Option<f32>,
 ) -> Vec<CollisionPair>
 {
-    let _span = info_span!("broad_phase", name = "compute SAP collisions").entered();
+    let _span = info_span!("broad_phase", name = "compute grid collisions").entered();
 
-    let count = entities.len();
+    let boxes = query.iter()
+        .filter(|(_e, _t, _a, _l, rb)| !is_static(*rb))
+        .map(|(e, _t, a, _l, _rb)| (e, a.fat_min, a.fat_max))
+        .collect::<Vec<_>>();
+    let grid = SpatialGrid::new(boxes, cell_size);
 
-    //fixme
-    let mut collision_pairs = vec!();
-    collision_pairs.extend(entities.iter().enumerate().flat_map(|(i, &entity1)|
-        {
-            let (e1, _p1, t1, a1) = query.get(entity1).unwrap();
-
-            let mut tmp_collision = Vec::<CollisionPair>::with_capacity(count - i - 1);
-            for &entity2 in entities.iter().skip(i + 1)
-            {
-                let (e2, _p2, t2, a2) = query.get(entity2).unwrap();
-                if t2.translation.x + a2.min.x > t1.translation.x + a1.max.x { break; };
-
-                if check_collision(&a1, &t1, &a2, &t2)
-                {
-                    tmp_collision.push(CollisionPair {
-                        entity_a: e1,
-                        entity_b: e2,
-                    });
-                }
-            }
-            return tmp_collision;
-        })
-        .collect::<Vec<_>>());
+    let collision_pairs = grid.compute_pairs(false).iter().filter_map(|&(entity_a, entity_b)| {
+        if !check_pair_filter(query, exclusions, entity_a, entity_b) {
+            return None;
+        }
+        return Some(CollisionPair {
+            entity_a,
+            entity_b,
+        });
+    }).collect::<Vec<_>>();
 
     return collision_pairs;
 }
+
+// Move the entities in the quadtree, rebuilding it if the scene grew out of its root
+pub(crate) fn update_quadtree(
+    query: &BroadPhaseQueryAwake,
+    quadtree: &mut LooseQuadtree,
+)
+{
+    let _span = info_span!("broad_phase", name = "update quadtree").entered();
+
+    quadtree.retain(|entity| is_moving_entity(query, entity));
+
+    let mut scene_min = Vec2::splat(f32::INFINITY);
+    let mut scene_max = Vec2::splat(f32::NEG_INFINITY);
+    for (e, _t, a, _l, _rb) in query.iter().filter(|(_e, _t, _a, _l, rb)| !is_static(*rb)) {
+        let (min, max) = (a.fat_min, a.fat_max);
+        scene_min = scene_min.min(min);
+        scene_max = scene_max.max(max);
+        quadtree.update(e, min, max);
+    }
+
+    if !quadtree.is_empty() && !quadtree.covers(scene_min, scene_max) {
+        quadtree.rebuild(scene_min, scene_max);
+    }
+}
+
+//Query the quadtree with every entity to create a vector of CollisionPair
+#[must_use]
+pub(crate) fn compute_collision_pairs_quadtree_mt(
+    query: &BroadPhaseQueryAwake,
+    exclusions: &PairExclusions,
+    quadtree: &LooseQuadtree,
+) -> Vec<CollisionPair>
+{
+    let _span = info_span!("broad_phase", name = "compute quadtree collisions").entered();
+
+    let collision_pairs = quadtree.compute_pairs(true).par_iter().filter_map(|&(entity_a, entity_b)| {
+        if !check_pair_filter(query, exclusions, entity_a, entity_b) {
+            return None;
+        }
+        return Some(CollisionPair {
+            entity_a,
+            entity_b,
+        });
+    }).collect::<Vec<_>>();
+
+    return collision_pairs;
+}
+
+//Query the quadtree with every entity to create a vector of CollisionPair
+#[must_use]
+pub(crate) fn compute_collision_pairs_quadtree_st(
+    query: &BroadPhaseQueryAwake,
+    exclusions: &PairExclusions,
+    quadtree: &LooseQuadtree,
+) -> Vec<CollisionPair>
+{
+    let _span = info_span!("broad_phase", name = "compute quadtree collisions").entered();
+
+    let collision_pairs = quadtree.compute_pairs(false).iter().filter_map(|&(entity_a, entity_b)| {
+        if !check_pair_filter(query, exclusions, entity_a, entity_b) {
+            return None;
+        }
+        return Some(CollisionPair {
+            entity_a,
+            entity_b,
+        });
+    }).collect::<Vec<_>>();
+
+    return collision_pairs;
+}
+
+// Static entities only go in their own tree, which doesn't change unless they are moved by hand
+pub(crate) fn update_static_tree(
+    query: &BroadPhaseQueryAwake,
+    static_tree: &mut DynamicTree,
+)
+{
+    let _span = info_span!("broad_phase", name = "update static tree").entered();
+
+    static_tree.retain(|entity| query.get(entity).map_or(false, |(_e, _t, _a, _l, rb)| is_static(rb)));
+
+    for (e, _t, a, _l, _rb) in query.iter().filter(|(_e, _t, _a, _l, rb)| is_static(*rb)) {
+        static_tree.update(e, a.fat_min, a.fat_max);
+    }
+}
+
+//Query the static tree with every dynamic entity, static entities never test each other
+#[must_use]
+pub(crate) fn compute_collision_pairs_static_mt(
+    query: &BroadPhaseQueryAwake,
+    exclusions: &PairExclusions,
+    static_tree: &DynamicTree,
+) -> Vec<CollisionPair>
+{
+    let _span = info_span!("broad_phase", name = "compute static collisions").entered();
+
+    if static_tree.is_empty() {
+        return vec!();
+    }
+
+    let dynamic_entities = query.iter()
+        .filter(|(_e, _t, _a, _l, rb)| get_body_type(*rb) == BodyType::Dynamic)
+        .collect::<Vec<_>>();
+
+    let collision_pairs = dynamic_entities.par_iter().flat_map_iter(|(e, _t, a, _l, _rb)| {
+        return static_tree.query(a.fat_min, a.fat_max).into_iter()
+            .filter(|&static_entity| check_pair_filter(query, exclusions, *e, static_entity))
+            .map(|static_entity| CollisionPair {
+                entity_a: *e,
+                entity_b: static_entity,
+            });
+    }).collect::<Vec<_>>();
+
+    return collision_pairs;
+}
+
+//Query the static tree with every dynamic entity, static entities never test each other
+#[must_use]
+pub(crate) fn compute_collision_pairs_static_st(
+    query: &BroadPhaseQueryAwake,
+    exclusions: &PairExclusions,
+    static_tree: &DynamicTree,
+) -> Vec<CollisionPair>
+{
+    let _span = info_span!("broad_phase", name = "compute static collisions").entered();
+
+    if static_tree.is_empty() {
+        return vec!();
+    }
+
+    let collision_pairs = query.iter()
+        .filter(|(_e, _t, _a, _l, rb)| get_body_type(*rb) == BodyType::Dynamic)
+        .flat_map(|(e, _t, a, _l, _rb)| {
+            return static_tree.query(a.fat_min, a.fat_max).into_iter()
+                .filter(move |&static_entity| check_pair_filter(query, exclusions, e, static_entity))
+                .map(move |static_entity| CollisionPair {
+                    entity_a: e,
+                    entity_b: static_entity,
+                });
+        }).collect::<Vec<_>>();
+
+    return collision_pairs;
+}
+
+// Colliders without a rigidbody can't be moved by the collision response, so they are static
+fn get_body_type(rigidbody: Option<&RigidBody2d>) -> BodyType {
+    return rigidbody.map_or(BodyType::Static, |rigidbody| rigidbody.body_type);
+}
+
+fn is_static(rigidbody: Option<&RigidBody2d>) -> bool {
+    return get_body_type(rigidbody) == BodyType::Static;
+}
+
+fn is_moving_entity(query: &BroadPhaseQueryAwake, entity: Entity) -> bool {
+    return query.get(entity).map_or(false, |(_e, _t, _a, _l, rb)| !is_static(rb));
+}
+
+// Pairs need a dynamic entity, and entities whose collision layers don't interact or which are explicitly excluded never make a pair
+fn check_pair_filter(
+    query: &BroadPhaseQueryAwake,
+    exclusions: &PairExclusions,
+    entity_a: Entity,
+    entity_b: Entity,
+) -> bool
+{
+    if exclusions.is_excluded(entity_a, entity_b) {
+        return false;
+    }
+
+    let Ok((_, _, _, layers_a, rigidbody_a)) = query.get(entity_a) else { return false; };
+    let Ok((_, _, _, layers_b, rigidbody_b)) = query.get(entity_b) else { return false; };
+    if get_body_type(rigidbody_a) != BodyType::Dynamic && get_body_type(rigidbody_b) != BodyType::Dynamic {
+        return false;
+    }
+    return check_layers(layers_a, layers_b);
+}
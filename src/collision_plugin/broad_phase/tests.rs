@@ -0,0 +1,294 @@
+// Correctness harness and benchmark of the broad phase backends.
+// Every backend is run through the real systems on seeded `random_poly` scenes and compared
+// against a brute force test of all the fat AABBs.
+//
+// Benchmark, printed as CSV on stdout:
+// cargo test --release bench_broad_phase -- --ignored --nocapture
+
+use std::collections::HashSet;
+use std::time::Instant;
+
+use bevy::ecs::schedule::{IntoSystemDescriptor, Stage, SystemStage};
+use bevy::prelude::*;
+
+use crate::collision_plugin::aabb::{AABB, check_bounds_overlap};
+use crate::collision_plugin::broad_phase::spatial_grid::SpatialGrid;
+use crate::collision_plugin::broad_phase::systems::broad_phase;
+use crate::collision_plugin::collision_layers::{check_layers, CollisionLayers, PairExclusions};
+use crate::collision_plugin::config::{BroadPhaseType, CollisionConfig};
+use crate::collision_plugin::data_structs::BroadPhaseData;
+use crate::collision_plugin::PhysicsAwake;
+use crate::collision_plugin::rigidbody::{BodyType, RigidBody2d};
+use crate::collision_plugin::systems::{aabb_update_system, heightfield_aabb_update_system};
+use crate::random_poly::{self, RandomPolyConfig};
+use crate::transform2d::Transform2d;
+
+const BACKENDS: [BroadPhaseType; 5] = [
+    BroadPhaseType::Rough,
+    BroadPhaseType::SAP,
+    BroadPhaseType::DynamicTree,
+    BroadPhaseType::Grid,
+    BroadPhaseType::Quadtree,
+];
+
+// Rough collects every combination, it is too slow past this
+const ROUGH_MAX_ENTITIES: usize = 1000;
+
+type Pairs = HashSet<(Entity, Entity)>;
+
+fn pair_key(entity_a: Entity, entity_b: Entity) -> (Entity, Entity) {
+    return if entity_a < entity_b { (entity_a, entity_b) } else { (entity_b, entity_a) };
+}
+
+// Scene of `count` random polygons with a constant density, surrounded by static walls and a heightfield.
+// Some polygons are kinematic, on other layers, in groups or excluded from each other, to exercise the filters.
+fn create_world(seed: u64, count: usize, backend: BroadPhaseType, multithread: bool) -> World {
+    fastrand::seed(seed);
+
+    let mut world = World::new();
+
+    let mut config = CollisionConfig::default();
+    config.phase_config.broad_phase_type = backend;
+    config.phase_config.multithread_broad_phase = multithread;
+    world.insert_resource(config);
+    world.init_resource::<BroadPhaseData>();
+    world.init_resource::<PairExclusions>();
+
+    let half_size = (count as f32).sqrt() * 25f32;
+    let poly_config = RandomPolyConfig {
+        min_bounds: Vec2::splat(-half_size),
+        max_bounds: Vec2::splat(half_size),
+        ..default()
+    };
+
+    let wall = half_size + 60f32;
+    world.spawn(random_poly::create_square(50f32, wall, Vec2::new(-wall, 0f32), 0f32, BodyType::Static));
+    world.spawn(random_poly::create_square(50f32, wall, Vec2::new(wall, 0f32), 0f32, BodyType::Static));
+    world.spawn(random_poly::create_square(wall, 50f32, Vec2::new(0f32, wall), 0f32, BodyType::Static));
+    world.spawn(random_poly::create_heightfield(
+        (0..20).map(|_| fastrand::f32() * 40f32).collect(),
+        wall / 10f32,
+        Vec2::new(-wall, -wall),
+    ));
+
+    let mut polygons = vec!();
+    for i in 0..count {
+        let (polygon, transform, aabb, mut rigidbody, awake) = random_poly::create_random_poly(&poly_config);
+        if i % 10 == 0 {
+            rigidbody.body_type = BodyType::Kinematic;
+        }
+
+        let mut entity = world.spawn((polygon, transform, aabb, rigidbody, awake));
+        match i % 7 {
+            0 => { entity.insert(CollisionLayers::new(0b01, 0b01)); }
+            1 => { entity.insert(CollisionLayers::new(0b10, 0b10)); }
+            2 => { entity.insert(CollisionLayers::default().with_group(-1)); }
+            3 => { entity.insert(CollisionLayers::new(0b01, 0b10).with_group(2)); }
+            _ => {}
+        }
+        polygons.push(entity.id());
+    }
+
+    let mut exclusions = world.resource_mut::<PairExclusions>();
+    for pair in polygons.chunks_exact(2).step_by(5) {
+        exclusions.exclude(pair[0], pair[1]);
+    }
+
+    return world;
+}
+
+fn create_stage() -> SystemStage {
+    return SystemStage::single_threaded()
+        .with_system(aabb_update_system)
+        .with_system(heightfield_aabb_update_system)
+        .with_system(broad_phase.after(aabb_update_system).after(heightfield_aabb_update_system));
+}
+
+// Move every non static entity a bit, and despawn some of them
+fn step_world(world: &mut World, step: usize) {
+    for (mut transform, rigidbody) in world.query::<(&mut Transform2d, &RigidBody2d)>().iter_mut(world) {
+        if rigidbody.body_type == BodyType::Static {
+            continue;
+        }
+        transform.translation += Vec2::new(fastrand::f32() - 0.5f32, fastrand::f32() - 0.5f32) * 10f32;
+        transform.rotation += (fastrand::f32() - 0.5f32) * 0.2f32;
+    }
+
+    if step % 3 == 2 {
+        let entities = world.query_filtered::<Entity, With<RigidBody2d>>().iter(world).collect::<Vec<_>>();
+        for entity in entities.into_iter().step_by(17) {
+            world.despawn(entity);
+        }
+    }
+}
+
+fn brute_force_pairs(world: &mut World) -> Pairs {
+    let mut query = world.query_filtered::<(Entity, &AABB, Option<&CollisionLayers>, Option<&RigidBody2d>), With<PhysicsAwake>>();
+    let exclusions = world.resource::<PairExclusions>();
+    let entities = query.iter(world).collect::<Vec<_>>();
+
+    let mut pairs = HashSet::new();
+    for (i, &(e1, a1, l1, rb1)) in entities.iter().enumerate() {
+        for &(e2, a2, l2, rb2) in &entities[i + 1..] {
+            let dynamic_1 = rb1.map_or(false, |rb| rb.body_type == BodyType::Dynamic);
+            let dynamic_2 = rb2.map_or(false, |rb| rb.body_type == BodyType::Dynamic);
+            if (dynamic_1 || dynamic_2)
+                && check_layers(l1, l2)
+                && !exclusions.is_excluded(e1, e2)
+                && check_bounds_overlap(a1.fat_min, a1.fat_max, a2.fat_min, a2.fat_max) {
+                pairs.insert(pair_key(e1, e2));
+            }
+        }
+    }
+    return pairs;
+}
+
+fn broad_phase_pairs(world: &World) -> (Pairs, usize) {
+    let collision_pairs = &world.resource::<BroadPhaseData>().collision_pairs;
+    let pairs = collision_pairs.iter()
+        .map(|pair| pair_key(pair.entity_a, pair.entity_b))
+        .collect::<HashSet<_>>();
+    return (pairs, collision_pairs.len());
+}
+
+fn check_backend(seed: u64, count: usize, backend: BroadPhaseType, multithread: bool) {
+    let mut world = create_world(seed, count, backend, multithread);
+    let mut stage = create_stage();
+
+    for step in 0..8 {
+        stage.run(&mut world);
+
+        let expected = brute_force_pairs(&mut world);
+        let (pairs, len) = broad_phase_pairs(&world);
+
+        // Rough doesn't test the AABBs, it only has to be a superset
+        let missing = expected.difference(&pairs).count();
+        let extra = if matches!(backend, BroadPhaseType::Rough) { 0 } else { pairs.difference(&expected).count() };
+        assert!(
+            missing == 0 && extra == 0 && len == pairs.len(),
+            "{:?} (multithread: {}) seed {} with {} entities, step {}: {} missing, {} extra, {} duplicated pairs",
+            backend, multithread, seed, count, step, missing, extra, len - pairs.len(),
+        );
+
+        step_world(&mut world, step);
+    }
+}
+
+#[test]
+fn broad_phase_backends_match_brute_force() {
+    for seed in [1, 2, 3] {
+        for count in [20, 300] {
+            for backend in BACKENDS {
+                for multithread in [false, true] {
+                    check_backend(seed, count, backend, multithread);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn broad_phase_backends_match_brute_force_large_scene() {
+    for backend in BACKENDS {
+        if matches!(backend, BroadPhaseType::Rough) {
+            continue;
+        }
+        for multithread in [false, true] {
+            check_backend(4, 800, backend, multithread);
+        }
+    }
+}
+
+// Persistent pairs must not survive a change of collision layers or body type
+#[test]
+fn broad_phase_backends_follow_filter_changes() {
+    for backend in BACKENDS {
+        for multithread in [false, true] {
+            let mut world = create_world(7, 300, backend, multithread);
+            let mut stage = create_stage();
+            stage.run(&mut world);
+
+            let entities = world.query_filtered::<Entity, With<RigidBody2d>>().iter(&world).collect::<Vec<_>>();
+            for (i, &entity) in entities.iter().enumerate() {
+                match i % 3 {
+                    0 => { world.entity_mut(entity).insert(CollisionLayers::new(0b100, 0b100)); }
+                    1 => { world.get_mut::<RigidBody2d>(entity).unwrap().body_type = BodyType::Kinematic; }
+                    _ => {}
+                }
+            }
+            stage.run(&mut world);
+
+            let expected = brute_force_pairs(&mut world);
+            let (pairs, len) = broad_phase_pairs(&world);
+            let missing = expected.difference(&pairs).count();
+            let extra = if matches!(backend, BroadPhaseType::Rough) { 0 } else { pairs.difference(&expected).count() };
+            assert!(
+                missing == 0 && extra == 0 && len == pairs.len(),
+                "{:?} (multithread: {}): {} missing, {} extra, {} duplicated pairs",
+                backend, multithread, missing, extra, len - pairs.len(),
+            );
+        }
+    }
+}
+
+// A tiny cell size would make every AABB cover millions of cells
+#[test]
+fn grid_cell_size_is_bounded_by_the_boxes() {
+    let boxes = (0..10)
+        .map(|i| (Entity::from_raw(i), Vec2::splat(i as f32 * 10f32), Vec2::splat(i as f32 * 10f32 + 50f32)))
+        .collect::<Vec<_>>();
+    assert!(SpatialGrid::new(boxes.clone(), Some(0.01f32)).cell_size() >= 50f32 / 8f32);
+    assert_eq!(SpatialGrid::new(boxes, Some(100f32)).cell_size(), 100f32);
+
+    // Mostly degenerate boxes and a large one
+    let mut boxes = (0..10)
+        .map(|i| (Entity::from_raw(i), Vec2::splat(i as f32), Vec2::splat(i as f32)))
+        .collect::<Vec<_>>();
+    boxes.push((Entity::from_raw(10), Vec2::ZERO, Vec2::splat(2000f32)));
+    assert!(SpatialGrid::new(boxes, None).cell_size() >= 2000f32 / 256f32);
+
+    let mut world = create_world(3, 300, BroadPhaseType::Grid, false);
+    let mut config = world.resource_mut::<CollisionConfig>();
+    config.phase_config.grid_auto_cell_size = false;
+    config.phase_config.grid_cell_size = 0.01f32;
+    create_stage().run(&mut world);
+
+    let expected = brute_force_pairs(&mut world);
+    let (pairs, len) = broad_phase_pairs(&world);
+    assert!(expected == pairs && len == pairs.len());
+}
+
+#[test]
+#[ignore]
+fn bench_broad_phase() {
+    const STEPS: usize = 20;
+
+    println!("backend,multithread,entities,pairs,mean_step_ms");
+    for count in [100, 500, 1000, 2000, 5000, 10000] {
+        for backend in BACKENDS {
+            if matches!(backend, BroadPhaseType::Rough) && count > ROUGH_MAX_ENTITIES {
+                continue;
+            }
+
+            for multithread in [false, true] {
+                let mut world = create_world(42, count, backend, multithread);
+                let mut stage = create_stage();
+                // The first step builds the persistent structures
+                stage.run(&mut world);
+
+                let mut total = 0f64;
+                for step in 0..STEPS {
+                    step_world(&mut world, step);
+                    let start = Instant::now();
+                    stage.run(&mut world);
+                    total += (Instant::now() - start).as_secs_f64();
+                }
+
+                println!("{:?},{},{},{},{:.4}",
+                         backend, multithread, count,
+                         world.resource::<BroadPhaseData>().collision_pairs.len(),
+                         total * 1000f64 / STEPS as f64);
+            }
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use bevy::prelude::*;
+
+use crate::collision_plugin::aabb::{AABB, check_bounds_overlap};
+use crate::collision_plugin::collision_layers::{check_layers, CollisionLayers, PairExclusions};
+use crate::collision_plugin::{ColliderFilter, Sensor};
+use crate::collision_plugin::config::{BroadPhaseType, CollisionConfig};
+use crate::collision_plugin::data_structs::BroadPhaseData;
+use crate::collision_plugin::heightfield_component::HeightfieldComponent;
+use crate::collision_plugin::narrow_phase::time_of_impact::{self, TimeOfImpact};
+use crate::collision_plugin::one_way_platform::OneWayPlatform;
+use crate::collision_plugin::polygon_component::{PolygonComponent, WorldPolygon};
+use crate::collision_plugin::rigidbody::{BodyType, RigidBody2d};
+use crate::collision_plugin::TIMESTEP;
+use crate::transform2d::Transform2d;
+
+/// Opt-in continuous collision detection. Instead of being teleported by its speed, the body is swept
+/// against static and kinematic colliders and other Ccd bodies, so fast bodies don't tunnel through thin walls.
+/// Sensors are never swept against. Kinematic bodies are followed along their speed, but one moved by setting
+/// its transform is only seen where it is at the start of the step, so a large enough move can still be tunneled through.
+/// Only dynamic bodies are swept, and rotations are ignored during the sweep.
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Ccd;
+
+pub type CcdBodyQuery<'w, 's> = Query<
+    'w, 's,
+    (
+        Entity,
+        &'static mut Transform2d,
+        &'static mut RigidBody2d,
+        &'static PolygonComponent,
+        &'static mut WorldPolygon,
+        Option<&'static CollisionLayers>,
+        Option<&'static Sensor>,
+    ),
+    With<Ccd>
+>;
+
+pub type CcdStaticQuery<'w, 's> = Query<
+    'w, 's,
+    (
+        Entity,
+        &'static Transform2d,
+        &'static AABB,
+        Option<&'static WorldPolygon>,
+        Option<&'static HeightfieldComponent>,
+        Option<&'static CollisionLayers>,
+        Option<&'static RigidBody2d>,
+        Option<&'static OneWayPlatform>,
+    ),
+    (ColliderFilter, Without<Ccd>, Without<Sensor>)
+>;
+
+// Earliest impact of a swept body, and how the other entity reacts to it
+struct Impact {
+    entity: Entity,
+    time_of_impact: TimeOfImpact,
+    other_velocity: Vec2,
+    other_inv_mass: f32,
+}
+
+// Start of step state of a swept body
+struct SweptBody {
+    entity: Entity,
+    velocity: Vec2,
+    inv_mass: f32,
+    layers: Option<CollisionLayers>,
+    sensor: bool,
+}
+
+// Colliders without a rigidbody can't be moved, so they are static
+fn get_body_type(rigidbody: Option<&RigidBody2d>) -> BodyType {
+    return rigidbody.map_or(BodyType::Static, |rigidbody| rigidbody.body_type);
+}
+
+// World space bounds of the polygon over its whole motion
+fn get_swept_bounds(polygon: &WorldPolygon, displacement: Vec2) -> (Vec2, Vec2) {
+    let (min, max) = polygon.get_bounds();
+    return (min + displacement.min(Vec2::ZERO), max + displacement.max(Vec2::ZERO));
+}
+
+fn keep_earliest(earliest: &mut Option<Impact>, impact: Impact) {
+    // Overlapping shapes are left to the narrow phase
+    if impact.time_of_impact.overlapping {
+        return;
+    }
+    if earliest.as_ref().map_or(true, |earliest| impact.time_of_impact.toi < earliest.time_of_impact.toi) {
+        *earliest = Some(impact);
+    }
+}
+
+// Earliest impact of the body before `max_toi`, `elapsed` into the step, against the static colliders and the other Ccd bodies.
+// `polygon` is the body at its current position, the other Ccd bodies are moved from their start of step pose by their speed.
+// Ccd bodies already hit during the step are skipped, their speed after that impact isn't known.
+// Kinematic bodies aren't in the static tree, they are all given in `kinematic_entities`
+fn find_impact(
+    body: &SweptBody,
+    hit: &[Entity],
+    kinematic_entities: &[Entity],
+    polygon: &WorldPolygon,
+    relative: &mut WorldPolygon,
+    velocity: Vec2,
+    elapsed: f32,
+    max_toi: f32,
+    ccd_query: &CcdBodyQuery,
+    static_query: &CcdStaticQuery,
+    broad_phase_data: &BroadPhaseData,
+    exclusions: &PairExclusions,
+    config: &CollisionConfig,
+) -> Option<Impact>
+{
+    let entity = body.entity;
+    let layers = body.layers.as_ref();
+    let (min, max) = get_swept_bounds(polygon, velocity * max_toi);
+
+    let mut earliest: Option<Impact> = None;
+
+    // The static tree isn't maintained when the broad phase is disabled
+    let immovable_entities = if matches!(config.phase_config.broad_phase_type, BroadPhaseType::Disabled) {
+        static_query.iter().map(|(entity, ..)| entity).collect::<Vec<_>>()
+    } else {
+        let mut entities = broad_phase_data.static_tree.query(min, max);
+        entities.extend_from_slice(kinematic_entities);
+        entities
+    };
+
+    for other in immovable_entities {
+        let Ok((_entity, t_other, aabb, p_other, h_other, l_other, rb_other, one_way_other)) = static_query.get(other) else { continue; };
+        if get_body_type(rb_other) == BodyType::Dynamic
+            || exclusions.is_excluded(entity, other)
+            || !check_layers(layers, l_other) {
+            continue;
+        }
+
+        // Kinematic bodies move by their speed, like the other Ccd bodies only the relative position matters
+        let other_velocity = rb_other.map_or(Vec2::ZERO, |rigidbody| rigidbody.linear_speed);
+        let other_offset = other_velocity * elapsed;
+        let (other_min, other_max) = (t_other.translate(aabb.min) + other_offset, t_other.translate(aabb.max) + other_offset);
+        let other_displacement = other_velocity * max_toi;
+        if !check_bounds_overlap(other_min + other_displacement.min(Vec2::ZERO), other_max + other_displacement.max(Vec2::ZERO), min, max) {
+            continue;
+        }
+
+        let moved_polygon = if other_offset == Vec2::ZERO {
+            polygon
+        } else {
+            relative.clone_from(polygon);
+            relative.translate(-other_offset);
+            &*relative
+        };
+
+        let time_of_impact = if let Some(p_other) = p_other {
+            time_of_impact::compute_time_of_impact(moved_polygon, velocity, p_other, other_velocity, max_toi)
+        } else if let Some(h_other) = h_other {
+            time_of_impact::compute_time_of_impact_heightfield(moved_polygon, velocity - other_velocity, h_other, t_other, max_toi)
+        } else {
+            None
+        };
+
+        // One way platforms let the bodies coming from the other side through
+        let time_of_impact = time_of_impact.filter(|time_of_impact| {
+            one_way_other.map_or(true, |platform| platform.is_blocking(t_other, time_of_impact.normal))
+        });
+
+        if let Some(time_of_impact) = time_of_impact {
+            keep_earliest(&mut earliest, Impact {
+                entity: other,
+                time_of_impact,
+                other_velocity,
+                other_inv_mass: 0f32,
+            });
+        }
+    }
+
+    for (other, _t, rb_other, _p, p_other, l_other, s_other) in ccd_query.iter() {
+        if other == entity || hit.contains(&other) || s_other.is_some() || exclusions.is_excluded(entity, other) || !check_layers(layers, l_other) {
+            continue;
+        }
+
+        // Only the relative position matters, so the body is moved back by the other's motion instead of copying the other
+        let other_velocity = rb_other.linear_speed;
+        let other_offset = other_velocity * elapsed;
+        let (other_min, other_max) = get_swept_bounds(p_other, other_velocity * max_toi);
+        if !check_bounds_overlap(other_min + other_offset, other_max + other_offset, min, max) {
+            continue;
+        }
+
+        relative.clone_from(polygon);
+        relative.translate(-other_offset);
+        if let Some(time_of_impact) = time_of_impact::compute_time_of_impact(relative, velocity, p_other, other_velocity, max_toi) {
+            keep_earliest(&mut earliest, Impact {
+                entity: other,
+                time_of_impact,
+                other_velocity,
+                other_inv_mass: rb_other.get_inv_mass(),
+            });
+        }
+    }
+
+    return earliest;
+}
+
+// Sub-step the body through the whole step, returns its displacement and its speed at the end of the step.
+// Bounces only change the speed of the swept body, the other Ccd body gets its share when it is swept itself
+fn sweep_body(
+    body: &SweptBody,
+    polygon: &mut WorldPolygon,
+    relative: &mut WorldPolygon,
+    kinematic_entities: &[Entity],
+    ccd_query: &CcdBodyQuery,
+    static_query: &CcdStaticQuery,
+    broad_phase_data: &BroadPhaseData,
+    exclusions: &PairExclusions,
+    config: &CollisionConfig,
+) -> (Vec2, Vec2)
+{
+    let mut velocity = body.velocity;
+    // Sensors go through everything
+    if body.sensor {
+        return (velocity * TIMESTEP as f32, velocity);
+    }
+
+    let (_e, _t, _rb, _p, start_polygon, _l, _s) = ccd_query.get(body.entity).unwrap();
+    polygon.clone_from(start_polygon);
+
+    let restitution = config.system_params.restitution;
+    let mut displacement = Vec2::ZERO;
+    let mut elapsed = 0f32;
+    let mut hit = vec!();
+
+    // Once out of sub-steps, the body stays where it last stopped for the rest of the step
+    for _ in 0..config.phase_config.ccd_max_substeps {
+        let remaining = TIMESTEP as f32 - elapsed;
+        let Some(impact) = find_impact(body, &hit, kinematic_entities, polygon, relative, velocity, elapsed, remaining,
+                                       ccd_query, static_query, broad_phase_data, exclusions, config) else {
+            displacement += velocity * remaining;
+            break;
+        };
+
+        let step = velocity * impact.time_of_impact.toi;
+        polygon.translate(step);
+        displacement += step;
+        elapsed += impact.time_of_impact.toi;
+        if ccd_query.contains(impact.entity) {
+            hit.push(impact.entity);
+        }
+
+        let normal = impact.time_of_impact.normal;
+        let normal_speed = (velocity - impact.other_velocity).dot(normal);
+        if normal_speed >= 0f32 {
+            continue;
+        }
+
+        let j = -(1f32 + restitution) * normal_speed / (body.inv_mass + impact.other_inv_mass);
+        velocity += j * body.inv_mass * normal;
+    }
+
+    return (displacement, velocity);
+}
+
+//Move the Ccd bodies by their speed, stopping at each impact to bounce off like the collision response would.
+//All the bodies are swept from their start of step pose, the moves are applied once they are all known
+pub(crate) fn continuous_collision_system(
+    mut ccd_query: CcdBodyQuery,
+    static_query: CcdStaticQuery,
+    broad_phase_data: Res<BroadPhaseData>,
+    exclusions: Res<PairExclusions>,
+    config: Res<CollisionConfig>,
+)
+{
+    let _span = info_span!("ccd", name = "continuous_collision").entered();
+
+    if !config.phase_config.ccd_enabled {
+        return;
+    }
+
+    // The collision response and the rotations moved the bodies since the caches were updated
+    for (_e, transform, _rb, polygon, mut world_polygon, _l, _s) in ccd_query.iter_mut() {
+        world_polygon.update(polygon, &transform);
+    }
+
+    let bodies = ccd_query.iter()
+        .filter(|(_e, _t, rb, _p, _w, _l, _s)| !rb.is_immovable())
+        .map(|(entity, _t, rb, _p, _w, layers, sensor)| SweptBody {
+            entity,
+            velocity: rb.linear_speed,
+            inv_mass: rb.get_inv_mass(),
+            layers: layers.copied(),
+            sensor: sensor.is_some(),
+        })
+        .collect::<Vec<_>>();
+
+    let kinematic_entities = static_query.iter()
+        .filter(|(_e, _t, _a, _p, _h, _l, rb, _o)| get_body_type(*rb) == BodyType::Kinematic)
+        .map(|(entity, ..)| entity)
+        .collect::<Vec<_>>();
+
+    // Scratch polygons, reused by every body
+    let mut polygon = WorldPolygon::default();
+    let mut relative = WorldPolygon::default();
+    let moves = bodies.iter()
+        .map(|body| sweep_body(body, &mut polygon, &mut relative, &kinematic_entities, &ccd_query, &static_query, &broad_phase_data, &exclusions, &config))
+        .collect::<Vec<_>>();
+
+    for (body, (displacement, velocity)) in bodies.iter().zip(moves) {
+        let (_e, mut transform, mut rigidbody, _p, mut world_polygon, _l, _s) = ccd_query.get_mut(body.entity).unwrap();
+        transform.translation += displacement;
+        rigidbody.linear_speed = velocity;
+        world_polygon.translate(displacement);
+    }
+}
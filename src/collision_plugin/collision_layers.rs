@@ -0,0 +1,108 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+
+/// Bitmasks selecting which entities can collide. Two entities collide only if each one's
+/// `memberships` share a bit with the other's `filters`. Entities without the component are in every layer.
+///
+/// Entities sharing the same non-zero `group` skip the masks, like Box2D's groupIndex:
+/// a positive group always collides, a negative one never does.
+#[derive(Component, Reflect, Clone, Copy, Debug, PartialEq, Eq)]
+#[reflect(Component)]
+pub struct CollisionLayers
+{
+    pub memberships: u32,
+    pub filters: u32,
+    pub group: i32,
+}
+
+impl CollisionLayers {
+    pub const ALL: u32 = u32::MAX;
+    pub const NONE: u32 = 0;
+
+    pub fn new(memberships: u32, filters: u32) -> Self {
+        return Self {
+            memberships,
+            filters,
+            group: 0,
+        };
+    }
+
+    pub fn with_group(mut self, group: i32) -> Self {
+        self.group = group;
+        return self;
+    }
+
+    pub fn interacts_with(&self, other: &CollisionLayers) -> bool {
+        if self.group != 0 && self.group == other.group {
+            return self.group > 0;
+        }
+
+        return (self.memberships & other.filters) != 0
+            && (other.memberships & self.filters) != 0;
+    }
+}
+
+impl Default for CollisionLayers {
+    fn default() -> Self {
+        CollisionLayers {
+            memberships: CollisionLayers::ALL,
+            filters: CollisionLayers::ALL,
+            group: 0,
+        }
+    }
+}
+
+/// Layer check for entities which may not have the component
+pub fn check_layers(layers_a: Option<&CollisionLayers>, layers_b: Option<&CollisionLayers>) -> bool {
+    return layers_a.cloned().unwrap_or_default().interacts_with(&layers_b.cloned().unwrap_or_default());
+}
+
+/// Pairs of entities that never collide, whatever their layers, such as bodies connected by a joint.
+/// Entities are forgotten once their collider is removed.
+#[derive(Default, Resource)]
+pub struct PairExclusions
+{
+    excluded: HashMap<Entity, HashSet<Entity>>,
+}
+
+impl PairExclusions {
+    pub fn exclude(&mut self, entity_a: Entity, entity_b: Entity) {
+        self.excluded.entry(entity_a).or_default().insert(entity_b);
+        self.excluded.entry(entity_b).or_default().insert(entity_a);
+    }
+
+    /// Allow the pair to collide again, returns false if it wasn't excluded
+    pub fn include(&mut self, entity_a: Entity, entity_b: Entity) -> bool {
+        let removed = self.remove_link(entity_a, entity_b);
+        self.remove_link(entity_b, entity_a);
+        return removed;
+    }
+
+    /// Forget every exclusion of the entity, only visiting its own partners
+    pub fn remove_entity(&mut self, entity: Entity) {
+        let Some(partners) = self.excluded.remove(&entity) else { return; };
+
+        for partner in partners {
+            self.remove_link(partner, entity);
+        }
+    }
+
+    pub fn is_excluded(&self, entity_a: Entity, entity_b: Entity) -> bool {
+        return self.excluded.get(&entity_a).map_or(false, |partners| partners.contains(&entity_b));
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        return self.excluded.contains_key(&entity);
+    }
+
+    fn remove_link(&mut self, entity: Entity, partner: Entity) -> bool {
+        let Some(partners) = self.excluded.get_mut(&entity) else { return false; };
+
+        let removed = partners.remove(&partner);
+        if partners.is_empty() {
+            self.excluded.remove(&entity);
+        }
+        return removed;
+    }
+}
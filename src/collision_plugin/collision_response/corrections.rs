@@ -4,33 +4,59 @@ use crate::collision_plugin::collision_response::ImpulseResult;
 use crate::collision_plugin::config::CollisionConfig;
 use crate::collision_plugin::data_structs::CollisionInfo;
 use crate::collision_plugin::helpers::Cross;
-use crate::collision_plugin::polygon_component::PolygonComponent;
+use crate::collision_plugin::ColliderFilter;
 use crate::collision_plugin::rigidbody::RigidBody2d;
 use crate::transform2d::Transform2d;
 
+/// Colliders without a rigidbody are part of the query, they behave like static bodies
+pub type ResponseQuery<'w, 's> = Query<
+    'w, 's,
+    (
+        &'static mut Transform2d,
+        Option<&'static mut RigidBody2d>,
+    ),
+    ColliderFilter
+>;
+
+// Colliders without a rigidbody have an infinite mass
+fn get_inv_mass(rigidbody: Option<&RigidBody2d>) -> f32 {
+    return rigidbody.map_or(0f32, |rigidbody| rigidbody.get_inv_mass());
+}
+
+fn get_speeds(rigidbody: Option<&RigidBody2d>) -> (Vec2, f32) {
+    return rigidbody.map_or((Vec2::ZERO, 0f32), |rigidbody| (rigidbody.linear_speed, rigidbody.angular_speed));
+}
+
+fn is_movable(rigidbody: Option<&RigidBody2d>) -> bool {
+    return rigidbody.map_or(false, |rigidbody| !rigidbody.is_immovable());
+}
+
 pub fn apply_position_correction(
     collision_info: &CollisionInfo,
-    query: &mut Query<(&mut Transform2d, &mut RigidBody2d), With<PolygonComponent>>,
+    query: &mut ResponseQuery,
     config: &Res<CollisionConfig>,
 )
 {
     if let Some(collision_pair) = collision_info.collision_pair {
-        let [(mut t1, rb1), (mut t2, rb2)]
-            = query.get_many_mut([collision_pair.entity_a, collision_pair.entity_b]).unwrap();
+        let Ok([(mut t1, rb1), (mut t2, rb2)])
+            = query.get_many_mut([collision_pair.entity_a, collision_pair.entity_b]) else { return; };
 
 
         let damping = config.system_params.damping;
 
-        let inv_mass_rb1 = rb1.get_inv_mass();
-        let inv_mass_rb2 = rb2.get_inv_mass();
+        let inv_mass_rb1 = get_inv_mass(rb1.as_deref());
+        let inv_mass_rb2 = get_inv_mass(rb2.as_deref());
+        if inv_mass_rb1 + inv_mass_rb2 == 0f32 {
+            return;
+        }
 
         let correction = (collision_info.distance * damping) / (inv_mass_rb1 + inv_mass_rb2);
 
-        if !rb1.is_kinematic {
+        if is_movable(rb1.as_deref()) {
             t1.translation += collision_info.normal * correction * inv_mass_rb1;
         }
 
-        if !rb2.is_kinematic {
+        if is_movable(rb2.as_deref()) {
             t2.translation -= collision_info.normal * correction * inv_mass_rb2;
         }
     }
@@ -38,21 +64,27 @@ pub fn apply_position_correction(
 
 pub fn compute_collision_impulse(
     collision_info: &CollisionInfo,
-    query: &Query<(&mut Transform2d, &mut RigidBody2d), With<PolygonComponent>>,
+    query: &ResponseQuery,
     config: &Res<CollisionConfig>,
 ) -> Option<(ImpulseResult, ImpulseResult)>
 {
     let collision_pair = &collision_info.collision_pair.unwrap();
 
-    let [
+    let Ok([
     (t1, rb1),
     (t2, rb2)
-    ] = query.get_many([collision_pair.entity_a, collision_pair.entity_b]).unwrap();
+    ]) = query.get_many([collision_pair.entity_a, collision_pair.entity_b]) else { return None; };
 
     let restitution = config.system_params.restitution;
 
-    let inv_mass_rb1 = rb1.get_inv_mass();
-    let inv_mass_rb2 = rb2.get_inv_mass();
+    let inv_mass_rb1 = get_inv_mass(rb1);
+    let inv_mass_rb2 = get_inv_mass(rb2);
+    if inv_mass_rb1 + inv_mass_rb2 == 0f32 {
+        return None;
+    }
+
+    let (linear_speed_1, angular_speed_1) = get_speeds(rb1);
+    let (linear_speed_2, angular_speed_2) = get_speeds(rb2);
 
     let mut impulse_a = ImpulseResult::default();
     impulse_a.entity = Some(collision_pair.entity_a);
@@ -66,8 +98,8 @@ pub fn compute_collision_impulse(
         let r_a = *collision_location - t1.translation;
         let r_b = *collision_location - t2.translation;
 
-        let v_a = rb1.linear_speed + impulse_a.linear_impulse - r_a.cross_float(rb1.angular_speed + impulse_a.angular_impulse);
-        let v_b = rb2.linear_speed + impulse_b.linear_impulse- r_b.cross_float(rb2.angular_speed + impulse_b.angular_impulse);
+        let v_a = linear_speed_1 + impulse_a.linear_impulse - r_a.cross_float(angular_speed_1 + impulse_a.angular_impulse);
+        let v_b = linear_speed_2 + impulse_b.linear_impulse- r_b.cross_float(angular_speed_2 + impulse_b.angular_impulse);
 
         let momentum_a = r_a.cross_vec(collision_info.normal) * inv_mass_rb1;
         let momentum_b = r_b.cross_vec(collision_info.normal) * inv_mass_rb2;
@@ -86,6 +118,9 @@ pub fn compute_collision_impulse(
 
             impulse_b.linear_impulse -= j * inv_mass_rb2 * collision_info.normal;
             impulse_b.angular_impulse -= j * momentum_b;
+
+            impulse_a.normal_impulse += j;
+            impulse_b.normal_impulse += j;
         }
     }
     if one_impulse {
@@ -96,7 +131,7 @@ pub fn compute_collision_impulse(
 
 pub fn apply_friction(
     collision_info: &CollisionInfo,
-    query: &mut Query<(&mut Transform2d, &mut RigidBody2d), With<PolygonComponent>>,
+    query: &mut ResponseQuery,
     config: &Res<CollisionConfig>,
 )
 {
@@ -104,14 +139,17 @@ pub fn apply_friction(
     let damping = config.system_params.damping;
     let friction = config.system_params.friction;
     let collision_pair = &collision_info.collision_pair.unwrap();
-    let [(t1, mut rb1), (t2, mut rb2)] = query.get_many_mut([collision_pair.entity_a, collision_pair.entity_b]).unwrap();
+    let Ok([(_t1, rb1), (_t2, rb2)]) = query.get_many_mut([collision_pair.entity_a, collision_pair.entity_b]) else { return; };
 
 
     let tangent = Vec2::new(-normal.y, normal.x);
-    let v_tangent = (rb1.linear_speed - rb2.linear_speed).dot(tangent);
+    let v_tangent = (get_speeds(rb1.as_deref()).0 - get_speeds(rb2.as_deref()).0).dot(tangent);
 
-    let inv_mass_rb1 = rb1.get_inv_mass();
-    let inv_mass_rb2 = rb2.get_inv_mass();
+    let inv_mass_rb1 = get_inv_mass(rb1.as_deref());
+    let inv_mass_rb2 = get_inv_mass(rb2.as_deref());
+    if inv_mass_rb1 + inv_mass_rb2 == 0f32 {
+        return;
+    }
 
     let collision_impulse = (collision_info.distance * damping) / (inv_mass_rb1 + inv_mass_rb2);
 
@@ -122,11 +160,11 @@ pub fn apply_friction(
 
     j = f32::clamp(j, -collision_impulse.abs() * (friction - f32::EPSILON), collision_impulse.abs() * (friction + f32::EPSILON));
 
-    if !rb1.is_kinematic {
+    if let Some(mut rb1) = rb1.filter(|rb1| !rb1.is_immovable()) {
         rb1.linear_speed += j * inv_mass_rb1 * tangent;
     }
 
-    if !rb2.is_kinematic {
+    if let Some(mut rb2) = rb2.filter(|rb2| !rb2.is_immovable()) {
         rb2.linear_speed -= j * inv_mass_rb2 * tangent;
     }
 }
\ No newline at end of file
@@ -8,4 +8,7 @@ pub struct ImpulseResult {
     pub entity: Option<Entity>,
     pub linear_impulse: Vec2,
     pub angular_impulse: f32,
-}
\ No newline at end of file
+    pub normal_impulse: f32,
+}
+#[cfg(test)]
+mod tests;
@@ -1,38 +1,50 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::Instant;
 
 use bevy::prelude::*;
 
 use crate::collision_plugin::collision_response;
 use crate::collision_plugin::collision_response::ImpulseResult;
+use crate::collision_plugin::collision_response::corrections::ResponseQuery;
 use crate::collision_plugin::config::CollisionConfig;
-use crate::collision_plugin::data_structs::{CollisionResponseData, NarrowPhaseData};
-use crate::collision_plugin::polygon_component::PolygonComponent;
-use crate::collision_plugin::rigidbody::RigidBody2d;
-use crate::transform2d::Transform2d;
+use crate::collision_plugin::data_structs::{CollisionInfo, CollisionResponseData, NarrowPhaseData};
+use crate::collision_plugin::events::pair_key;
+use crate::collision_plugin::Sensor;
 
 pub(crate) fn collision_response(
     narrow_phase_data: ResMut<NarrowPhaseData>,
     mut collision_response_data: ResMut<CollisionResponseData>,
-    mut query: Query<(&mut Transform2d, &mut RigidBody2d), With<PolygonComponent>>,
+    mut query: ResponseQuery,
+    q_sensors: Query<(), With<Sensor>>,
     config: Res<CollisionConfig>,
 )
 {
     let _span = info_span!("collision_response", name = "System").entered();
     let start = Instant::now();
 
-    apply_response_st(&narrow_phase_data, &mut query, &config);
-    // apply_response_mt(narrow_phase_data, &mut query, &config);    // FAILED ATTEMPT AT MULTITHREADING
+    let mut total_impulses = vec!(0f32; narrow_phase_data.collision_infos.len());
+    apply_response_st(&narrow_phase_data, &collision_response_data.passing_pairs, &mut query, &q_sensors, &config, &mut total_impulses);
+    // apply_response_mt(narrow_phase_data, &collision_response_data.passing_pairs, &mut query, &q_sensors, &config);    // FAILED ATTEMPT AT MULTITHREADING
+    collision_response_data.total_impulses = total_impulses;
 
 
     collision_response_data.time += Instant::now() - start;
 }
 
-pub(crate) fn apply_response_mt(narrow_phase_data: ResMut<NarrowPhaseData>, mut query: &mut Query<(&mut Transform2d, &mut RigidBody2d), With<PolygonComponent>>, config: &Res<CollisionConfig>) {
+//Contacts with a sensor, or going through a one way platform, are only reported by the collision events
+fn is_ignored_contact(collision_info: &CollisionInfo, passing_pairs: &HashSet<(Entity, Entity)>, q_sensors: &Query<(), With<Sensor>>) -> bool {
+    let Some(collision_pair) = collision_info.collision_pair else { return false; };
+    return q_sensors.contains(collision_pair.entity_a)
+        || q_sensors.contains(collision_pair.entity_b)
+        || passing_pairs.contains(&pair_key(collision_pair.entity_a, collision_pair.entity_b));
+}
+
+pub(crate) fn apply_response_mt(narrow_phase_data: ResMut<NarrowPhaseData>, passing_pairs: &HashSet<(Entity, Entity)>, mut query: &mut ResponseQuery, q_sensors: &Query<(), With<Sensor>>, config: &Res<CollisionConfig>) {
     let _span = info_span!("collision_response", name = "apply_multi_thread").entered();
 
     for _ in 0..64 {
         let impulses = narrow_phase_data.collision_infos.iter()
+            .filter(|collision_info| !is_ignored_contact(collision_info, passing_pairs, q_sensors))
             .filter_map(|collision_info| {
                 if let Some((imp_a, imp_b)) = collision_response::corrections::compute_collision_impulse(collision_info, &query, &config) {
                     return Some([imp_a, imp_b]);
@@ -58,8 +70,8 @@ pub(crate) fn apply_response_mt(narrow_phase_data: ResMut<NarrowPhaseData>, mut
         });
 
         impulse_set.iter().for_each(|(e, imp)| {
-            let (_, mut rb) = query.get_mut(*e).unwrap();
-            if !rb.is_kinematic {
+            let Ok((_, Some(mut rb))) = query.get_mut(*e) else { return; };
+            if !rb.is_immovable() {
                 let maxlin = Vec2::new(10f32, 10f32);
                 let maxang = 10f32;
                 rb.linear_speed += imp.linear_impulse.clamp(-maxlin, maxlin);
@@ -68,32 +80,47 @@ pub(crate) fn apply_response_mt(narrow_phase_data: ResMut<NarrowPhaseData>, mut
         });
     }
 
-    narrow_phase_data.collision_infos.iter().for_each(|collision_info| {
-        collision_response::corrections::apply_position_correction(collision_info, &mut query, &config);
-    });
+    narrow_phase_data.collision_infos.iter()
+        .filter(|collision_info| !is_ignored_contact(collision_info, passing_pairs, q_sensors))
+        .for_each(|collision_info| {
+            collision_response::corrections::apply_position_correction(collision_info, &mut query, &config);
+        });
 }
 
-pub(crate) fn apply_response_st(narrow_phase_data: &ResMut<NarrowPhaseData>, mut query: &mut Query<(&mut Transform2d, &mut RigidBody2d), With<PolygonComponent>>, config: &Res<CollisionConfig>) {
+pub(crate) fn apply_response_st(narrow_phase_data: &ResMut<NarrowPhaseData>, passing_pairs: &HashSet<(Entity, Entity)>, mut query: &mut ResponseQuery, q_sensors: &Query<(), With<Sensor>>, config: &Res<CollisionConfig>, total_impulses: &mut Vec<f32>) {
     let _span = info_span!("collision_response", name = "apply_single_thread").entered();
 
+    let is_solid = narrow_phase_data.collision_infos.iter()
+        .map(|collision_info| !is_ignored_contact(collision_info, passing_pairs, q_sensors))
+        .collect::<Vec<_>>();
+
     for _ in 0..16 {
-        for collision_info in &narrow_phase_data.collision_infos {
+        for (index, collision_info) in narrow_phase_data.collision_infos.iter().enumerate() {
+            if !is_solid[index] {
+                continue;
+            }
             if let Some((imp_a, imp_b)) = collision_response::corrections::compute_collision_impulse(collision_info, &query, &config) {
-                let (_, mut rb) = query.get_mut(imp_a.entity.unwrap()).unwrap();
-                if !rb.is_kinematic {
-                    rb.linear_speed += imp_a.linear_impulse;
-                    rb.angular_speed += imp_a.angular_impulse;
+                total_impulses[index] += imp_a.normal_impulse;
+                if let Ok((_, Some(mut rb))) = query.get_mut(imp_a.entity.unwrap()) {
+                    if !rb.is_immovable() {
+                        rb.linear_speed += imp_a.linear_impulse;
+                        rb.angular_speed += imp_a.angular_impulse;
+                    }
                 }
-                let (_, mut rb) = query.get_mut(imp_b.entity.unwrap()).unwrap();
-                if !rb.is_kinematic {
-                    rb.linear_speed += imp_b.linear_impulse;
-                    rb.angular_speed += imp_b.angular_impulse;
+                if let Ok((_, Some(mut rb))) = query.get_mut(imp_b.entity.unwrap()) {
+                    if !rb.is_immovable() {
+                        rb.linear_speed += imp_b.linear_impulse;
+                        rb.angular_speed += imp_b.angular_impulse;
+                    }
                 }
             }
         }
     }
 
-    for collision_info in &narrow_phase_data.collision_infos {
+    for (index, collision_info) in narrow_phase_data.collision_infos.iter().enumerate() {
+        if !is_solid[index] {
+            continue;
+        }
         collision_response::corrections::apply_friction(collision_info, &mut query, &config);
         collision_response::corrections::apply_position_correction(collision_info, &mut query, &config);
     }
@@ -0,0 +1,123 @@
+// Colliders without a rigidbody are treated as static by the broad phase, the response must not expect one.
+// Contacts ignored by the response are still left for the collision events
+
+use bevy::ecs::schedule::{IntoSystemDescriptor, Stage, SystemStage};
+use bevy::prelude::*;
+
+use crate::collision_plugin::broad_phase::systems::broad_phase;
+use crate::collision_plugin::collision_layers::PairExclusions;
+use crate::collision_plugin::collision_response::systems::collision_response;
+use crate::collision_plugin::config::CollisionConfig;
+use crate::collision_plugin::data_structs::{BroadPhaseData, CollisionResponseData, NarrowPhaseData};
+use crate::collision_plugin::heightfield_component::HeightfieldComponent;
+use crate::collision_plugin::narrow_phase::systems::narrow_phase;
+use crate::collision_plugin::one_way_platform::{one_way_platform_system, OneWayPlatform};
+use crate::collision_plugin::PhysicsAwake;
+use crate::collision_plugin::polygon_component::WorldPolygon;
+use crate::collision_plugin::rigidbody::{BodyType, RigidBody2d};
+use crate::collision_plugin::systems::{aabb_update_system, heightfield_aabb_update_system, world_polygon_update_system};
+use crate::random_poly;
+use crate::transform2d::Transform2d;
+
+fn create_world() -> World {
+    let mut world = World::new();
+    world.init_resource::<CollisionConfig>();
+    world.init_resource::<BroadPhaseData>();
+    world.init_resource::<NarrowPhaseData>();
+    world.init_resource::<CollisionResponseData>();
+    world.init_resource::<PairExclusions>();
+    return world;
+}
+
+fn create_stage() -> SystemStage {
+    return SystemStage::single_threaded()
+        .with_system(aabb_update_system)
+        .with_system(heightfield_aabb_update_system)
+        .with_system(world_polygon_update_system)
+        .with_system(broad_phase.after(aabb_update_system).after(heightfield_aabb_update_system).after(world_polygon_update_system))
+        .with_system(narrow_phase.after(broad_phase))
+        .with_system(one_way_platform_system.after(narrow_phase))
+        .with_system(collision_response.after(one_way_platform_system));
+}
+
+// Dynamic square falling into the collider, returns its entity
+fn spawn_falling_square(world: &mut World, position: Vec2) -> Entity {
+    let (polygon, transform, aabb, mut rigidbody, awake) = random_poly::create_square(10f32, 10f32, position, 0f32, BodyType::Dynamic);
+    rigidbody.linear_speed = Vec2::new(0f32, -50f32);
+    let world_polygon = WorldPolygon::new(&polygon, &transform);
+    return world.spawn((polygon, transform, aabb, rigidbody, awake, world_polygon)).id();
+}
+
+// Drop a square on another one, with or without a static rigidbody, returns the translation of the collider,
+// and the translation and speed of the falling square after a step
+fn drop_square_on_collider(with_rigidbody: bool) -> (Vec2, Vec2, Vec2) {
+    let mut world = create_world();
+
+    let (polygon, transform, aabb, rigidbody, awake) = random_poly::create_square(10f32, 10f32, Vec2::ZERO, 0f32, BodyType::Static);
+    let world_polygon = WorldPolygon::new(&polygon, &transform);
+    let mut collider = world.spawn((polygon, transform, aabb, awake, world_polygon));
+    if with_rigidbody {
+        collider.insert(rigidbody);
+    }
+    let collider = collider.id();
+    let body = spawn_falling_square(&mut world, Vec2::new(0f32, 15f32));
+
+    create_stage().run(&mut world);
+
+    assert_eq!(world.resource::<NarrowPhaseData>().collision_infos.len(), 1);
+    return (
+        world.get::<Transform2d>(collider).unwrap().translation,
+        world.get::<Transform2d>(body).unwrap().translation,
+        world.get::<RigidBody2d>(body).unwrap().linear_speed,
+    );
+}
+
+#[test]
+fn collider_without_rigidbody_is_static() {
+    let (collider_translation, body_translation, body_speed) = drop_square_on_collider(false);
+
+    assert_eq!(collider_translation, Vec2::ZERO);
+    assert!(body_translation.y > 15f32);
+    assert!(body_speed.y > -50f32);
+    assert_eq!((collider_translation, body_translation, body_speed), drop_square_on_collider(true));
+}
+
+#[test]
+fn heightfield_without_rigidbody_is_static() {
+    let mut world = create_world();
+
+    let heightfield = HeightfieldComponent::new(vec!(0f32; 5), 10f32);
+    let transform = Transform2d {
+        translation: Vec2::new(-20f32, 0f32),
+        rotation: 0f32,
+        scale: 1f32,
+    };
+    let aabb = heightfield.get_aabb(&transform);
+    world.spawn((heightfield, transform, aabb, PhysicsAwake));
+    let body = spawn_falling_square(&mut world, Vec2::new(0f32, 5f32));
+
+    create_stage().run(&mut world);
+
+    assert!(!world.resource::<NarrowPhaseData>().collision_infos.is_empty());
+    assert!(world.get::<Transform2d>(body).unwrap().translation.y > 5f32);
+    assert!(world.get::<RigidBody2d>(body).unwrap().linear_speed.y >= 0f32);
+}
+
+#[test]
+fn body_going_through_one_way_platform_keeps_its_contact() {
+    let mut world = create_world();
+
+    let (polygon, transform, aabb, _rigidbody, awake) = random_poly::create_square(10f32, 10f32, Vec2::ZERO, 0f32, BodyType::Static);
+    let world_polygon = WorldPolygon::new(&polygon, &transform);
+    world.spawn((polygon, transform, aabb, awake, world_polygon, OneWayPlatform::default()));
+    let body = spawn_falling_square(&mut world, Vec2::new(0f32, -15f32));
+    world.get_mut::<RigidBody2d>(body).unwrap().linear_speed = Vec2::new(0f32, 50f32);
+
+    create_stage().run(&mut world);
+
+    // The contact is left for the collision events, but the body isn't pushed back
+    assert_eq!(world.resource::<NarrowPhaseData>().collision_infos.len(), 1);
+    assert_eq!(world.resource::<CollisionResponseData>().passing_pairs.len(), 1);
+    assert_eq!(world.get::<Transform2d>(body).unwrap().translation, Vec2::new(0f32, -15f32));
+    assert_eq!(world.get::<RigidBody2d>(body).unwrap().linear_speed, Vec2::new(0f32, 50f32));
+}
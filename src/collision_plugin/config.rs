@@ -1,12 +1,15 @@
 use bevy::prelude::*;
 use bevy_inspector_egui::Inspectable;
 
-#[derive(Inspectable, Default)]
+#[derive(Inspectable, Default, Clone, Copy, Debug)]
 pub enum BroadPhaseType {
     Disabled,
     Rough,
     #[default]
     SAP,
+    DynamicTree,
+    Grid,
+    Quadtree,
 }
 
 #[derive(Inspectable, Default)]
@@ -15,6 +18,8 @@ pub enum NarrowPhaseType {
     SAT,
     #[default]
     GJK,
+    /// Cheaper than GJK with EPA, but the depth is not the minimum translation, so deep overlaps are pushed out inaccurately
+    MPR,
 }
 
 #[derive(Inspectable)]
@@ -22,8 +27,21 @@ pub struct PhaseConfig {
     pub broad_phase_type: BroadPhaseType,
     pub narrow_phase_type: NarrowPhaseType,
 
+    pub grid_auto_cell_size: bool,
+    #[inspectable(label = "grid_cell_size (if not auto)", min = 1.0)]
+    pub grid_cell_size: f32,
+
+    #[inspectable(min = 0.0)]
+    pub aabb_margin: f32,
+    #[inspectable(label = "aabb_prediction_steps (0 = no prediction)", min = 0.0)]
+    pub aabb_prediction_steps: f32,
+
     pub compute_info_collision: bool,
 
+    pub ccd_enabled: bool,
+    #[inspectable(min = 1)]
+    pub ccd_max_substeps: usize,
+
     pub multithread_broad_phase: bool,
     pub multithread_narrow_phase: bool,
     #[inspectable(label = "multithread_response_phase\n(Bugged: collisions explode due tu lack of clamping)")]
@@ -31,12 +49,28 @@ pub struct PhaseConfig {
     pub multithread_response_phase: bool,
 }
 
+impl PhaseConfig {
+    /// Cell size given to the Grid broad phase, None when it is deduced from the AABBs
+    pub fn get_grid_cell_size(&self) -> Option<f32> {
+        if self.grid_auto_cell_size {
+            return None;
+        }
+        return Some(self.grid_cell_size);
+    }
+}
+
 impl Default for PhaseConfig {
     fn default() -> Self {
         PhaseConfig{
             broad_phase_type: Default::default(),
             narrow_phase_type: Default::default(),
+            grid_auto_cell_size: true,
+            grid_cell_size: 100f32,
+            aabb_margin: 2f32,
+            aabb_prediction_steps: 2f32,
             compute_info_collision: true,
+            ccd_enabled: true,
+            ccd_max_substeps: 4,
             multithread_broad_phase: true,
             multithread_narrow_phase: true,
             multithread_response_phase: false,
@@ -50,6 +84,7 @@ pub struct DebugDrawing {
     pub draw_debug_narrow_phase: bool,
     pub draw_debug_aabb: bool,
     pub draw_debug_rigidbody: bool,
+    pub draw_debug_distance: bool,
 }
 
 #[derive(Inspectable)]
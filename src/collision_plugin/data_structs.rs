@@ -1,9 +1,14 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
 use bevy::prelude::*;
 use bevy_inspector_egui::Inspectable;
 
+use crate::collision_plugin::broad_phase::dynamic_tree::DynamicTree;
+use crate::collision_plugin::broad_phase::quadtree::LooseQuadtree;
+use crate::collision_plugin::broad_phase::sweep_and_prune::SweepAndPrune;
+use crate::collision_plugin::events::CollisionContact;
+
 #[derive(Clone, Copy, Inspectable, Debug)]
 pub struct CollisionPair {
     pub entity_a: Entity,
@@ -14,6 +19,10 @@ pub struct CollisionPair {
 pub struct CollisionInfo {
     pub collision_pair: Option<CollisionPair>,
     pub location: Vec<Vec2>,
+    /// Penetration depth at each location
+    pub depths: Vec<f32>,
+    /// Identifies the polygon features which made each location, stable from one step to the next
+    pub feature_ids: Vec<u32>,
     pub normal: Vec2,
     pub distance: f32,
 }
@@ -30,8 +39,14 @@ impl CollisionInfo {
 
 #[derive(Default, Resource)]
 pub struct BroadPhaseData {
+    /// Pairs of the SAP come first and are updated in place, the other pairs are computed again every step
     pub collision_pairs: Vec<CollisionPair>,
-    pub sorted_entities: Vec<Entity>,
+    pub awake_entities: Vec<Entity>,
+    pub dynamic_tree: DynamicTree,
+    /// Static entities, only queried by the dynamic ones
+    pub static_tree: DynamicTree,
+    pub sweep_and_prune: SweepAndPrune,
+    pub quadtree: LooseQuadtree,
     pub time: Duration,
 }
 
@@ -44,5 +59,10 @@ pub struct NarrowPhaseData {
 
 #[derive(Default, Resource)]
 pub struct CollisionResponseData {
+    /// Normal impulse applied for each of the NarrowPhaseData's collision infos
+    pub total_impulses: Vec<f32>,
+    pub previous_contacts: HashMap<(Entity, Entity), CollisionContact>,
+    /// Pairs going through a one way platform, ignored until they stop touching
+    pub passing_pairs: HashSet<(Entity, Entity)>,
     pub time: Duration,
 }
\ No newline at end of file
@@ -5,7 +5,9 @@ use bevy::prelude::*;
 use crate::collision_plugin::aabb::AABB;
 use crate::collision_plugin::config::CollisionConfig;
 use crate::collision_plugin::data_structs::{BroadPhaseData, CollisionResponseData, NarrowPhaseData};
-use crate::collision_plugin::polygon_component::PolygonComponent;
+use crate::collision_plugin::heightfield_component::HeightfieldComponent;
+use crate::collision_plugin::narrow_phase::gjk;
+use crate::collision_plugin::polygon_component::{PolygonComponent, WorldPolygon};
 use crate::collision_plugin::rendering::{LineBatch, LineBatches};
 use crate::collision_plugin::rigidbody::RigidBody2d;
 use crate::transform2d::Transform2d;
@@ -28,12 +30,13 @@ pub(crate) fn update_debug_info(
     config.statistics.total_frame_time = time.delta_seconds() * 1000f32;
 
     config.statistics.collision_pairs_count = broad_phase_data.collision_pairs.len();
-    config.statistics.awake_entities_count = broad_phase_data.sorted_entities.len();
+    config.statistics.awake_entities_count = broad_phase_data.awake_entities.len();
 }
 
 pub(crate) fn refresh_polygon_lines(
     mut line_batches: ResMut<LineBatches>,
     polygon_query: Query<(&PolygonComponent, &Transform2d)>,
+    heightfield_query: Query<(&HeightfieldComponent, &Transform2d)>,
 )
 {
     let mut batches = Vec::<LineBatch>::new();
@@ -43,7 +46,7 @@ pub(crate) fn refresh_polygon_lines(
     let mut colliding_batch = LineBatch::new(Color::RED);
 
     for (p, t) in &polygon_query {
-        let mut points = p.get_transformed_points(t);
+        let mut points = p.get_outline_points(t);
 
         points.push(points[0].clone());
 
@@ -66,6 +69,17 @@ pub(crate) fn refresh_polygon_lines(
         }
     }
 
+    for (h, t) in &heightfield_query {
+        let points = h.get_surface_points(t).iter().map(|p| p.extend(0f32)).collect::<Vec<_>>();
+
+        if !non_colliding_batch.try_push_vertices(&points)
+        {
+            batches.push(non_colliding_batch);
+            non_colliding_batch = LineBatch::new(Color::GREEN);
+            non_colliding_batch.try_push_vertices(&points);
+        }
+    }
+
     if !colliding_batch.is_empty() {
         batches.push(colliding_batch);
     }
@@ -78,6 +92,8 @@ pub(crate) fn refresh_polygon_lines(
 
 pub(crate) fn draw_debug(
     mut query: Query<(Entity, &mut PolygonComponent, &Transform2d, &RigidBody2d, &AABB)>,
+    transform_query: Query<&Transform2d>,
+    world_polygon_query: Query<&WorldPolygon>,
     narrow_phase_data: ResMut<NarrowPhaseData>,
     broad_phase_data: ResMut<BroadPhaseData>,
     mut line_batches: ResMut<LineBatches>,
@@ -143,8 +159,7 @@ pub(crate) fn draw_debug(
         let mut broad_phase_batch = LineBatch::new(Color::YELLOW);
 
         for pair in &broad_phase_data.collision_pairs {
-            let (_, p1, t1, rb, a1) = query.get(pair.entity_a).unwrap();
-            let (_, p2, t2, rb, a2) = query.get(pair.entity_b).unwrap();
+            let (Ok(t1), Ok(t2)) = (transform_query.get(pair.entity_a), transform_query.get(pair.entity_b)) else { continue; };
             let points = vec!(
                 t1.translation.extend(0f32),
                 t2.translation.extend(0f32),
@@ -162,19 +177,42 @@ pub(crate) fn draw_debug(
         }
     }
 
+    if config.debug_drawing.draw_debug_distance
+    {
+        let mut distance_batch = LineBatch::new(Color::ORANGE);
+
+        for pair in &broad_phase_data.collision_pairs {
+            let (Ok(p1), Ok(p2)) = (world_polygon_query.get(pair.entity_a), world_polygon_query.get(pair.entity_b)) else { continue; };
+            let Some(distance_info) = gjk::get_distance(p1, p2) else { continue; };
+
+            let points = vec!(
+                distance_info.point_a.extend(0f32),
+                distance_info.point_b.extend(0f32),
+            );
+            if !distance_batch.try_push_vertices(&points)
+            {
+                batches.push(distance_batch);
+                distance_batch = LineBatch::new(Color::ORANGE);
+                distance_batch.try_push_vertices(&points);
+            }
+        }
+
+        if !distance_batch.is_empty() {
+            batches.push(distance_batch);
+        }
+    }
+
     if config.debug_drawing.draw_debug_narrow_phase {
         let mut narrow_phase_batch = LineBatch::new(Color::CYAN);
 
         for collision_info in &narrow_phase_data.collision_infos {
             if let Some(pair) = collision_info.collision_pair
             {
-                {
-                    let (_, mut p1, t1, rb, a1) = query.get_mut(pair.entity_a).unwrap();
+                if let Ok((_, mut p1, t1, rb, a1)) = query.get_mut(pair.entity_a) {
                     p1.collided = true;
                 }
 
-                {
-                    let (_, mut p2, t2, rb, a2) = query.get_mut(pair.entity_b).unwrap();
+                if let Ok((_, mut p2, t2, rb, a2)) = query.get_mut(pair.entity_b) {
                     p2.collided = true;
                 }
 
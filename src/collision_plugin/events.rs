@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::collision_plugin::data_structs::{CollisionResponseData, NarrowPhaseData};
+
+/// Contact between two entities for the current step.
+/// The normal pushes `entity_a` away from `entity_b`, like in CollisionInfo.
+#[derive(Clone, Debug)]
+pub struct CollisionContact {
+    pub entity_a: Entity,
+    pub entity_b: Entity,
+    pub normal: Vec2,
+    pub contact_points: Vec<Vec2>,
+    /// Penetration depth at each contact point
+    pub contact_depths: Vec<f32>,
+    /// Stable identifier of each contact point, see CollisionInfo
+    pub feature_ids: Vec<u32>,
+    /// Sum of the normal impulses applied by the collision response
+    pub total_impulse: f32,
+}
+
+/// The two entities started colliding this step
+#[derive(Clone, Debug)]
+pub struct CollisionStarted(pub CollisionContact);
+
+/// The two entities were already colliding on the previous step
+#[derive(Clone, Debug)]
+pub struct CollisionPersisted(pub CollisionContact);
+
+/// The two entities stopped colliding this step, the contact is the last one that was reported.
+/// One of the entities may have been despawned.
+#[derive(Clone, Debug)]
+pub struct CollisionEnded(pub CollisionContact);
+
+pub(crate) fn pair_key(entity_a: Entity, entity_b: Entity) -> (Entity, Entity) {
+    if entity_a < entity_b {
+        return (entity_a, entity_b);
+    }
+    return (entity_b, entity_a);
+}
+
+// Gather the collision infos of the step by pair, heightfields report one info per cell
+fn collect_contacts(
+    narrow_phase_data: &NarrowPhaseData,
+    collision_response_data: &CollisionResponseData,
+) -> HashMap<(Entity, Entity), CollisionContact>
+{
+    let mut contacts: HashMap<(Entity, Entity), CollisionContact> = HashMap::new();
+    let mut deepest: HashMap<(Entity, Entity), f32> = HashMap::new();
+
+    for (index, collision_info) in narrow_phase_data.collision_infos.iter().enumerate() {
+        let Some(pair) = collision_info.collision_pair else { continue; };
+        let key = pair_key(pair.entity_a, pair.entity_b);
+        let total_impulse = collision_response_data.total_impulses.get(index).cloned().unwrap_or(0f32);
+
+        let contact = contacts.entry(key).or_insert_with(|| CollisionContact {
+            entity_a: pair.entity_a,
+            entity_b: pair.entity_b,
+            normal: collision_info.normal,
+            contact_points: vec!(),
+            contact_depths: vec!(),
+            feature_ids: vec!(),
+            total_impulse: 0f32,
+        });
+
+        contact.contact_points.extend(collision_info.location.iter().cloned());
+        contact.contact_depths.extend(collision_info.depths.iter().cloned());
+        contact.feature_ids.extend(collision_info.feature_ids.iter().cloned());
+        contact.total_impulse += total_impulse;
+
+        // The deepest info gives the normal of the contact
+        let depth = deepest.entry(key).or_insert(collision_info.distance);
+        if collision_info.distance > *depth {
+            *depth = collision_info.distance;
+            contact.normal = collision_info.normal;
+        }
+    }
+
+    return contacts;
+}
+
+//Diff the colliding pairs of this step with the previous ones to send the collision events
+pub(crate) fn send_collision_events(
+    narrow_phase_data: Res<NarrowPhaseData>,
+    mut collision_response_data: ResMut<CollisionResponseData>,
+    mut started_events: EventWriter<CollisionStarted>,
+    mut persisted_events: EventWriter<CollisionPersisted>,
+    mut ended_events: EventWriter<CollisionEnded>,
+)
+{
+    let _span = info_span!("collision_response", name = "send_collision_events").entered();
+
+    let contacts = collect_contacts(&narrow_phase_data, &collision_response_data);
+    let previous_contacts = std::mem::take(&mut collision_response_data.previous_contacts);
+
+    for (key, contact) in &contacts {
+        if previous_contacts.contains_key(key) {
+            persisted_events.send(CollisionPersisted(contact.clone()));
+        } else {
+            started_events.send(CollisionStarted(contact.clone()));
+        }
+    }
+
+    for (key, contact) in previous_contacts {
+        if !contacts.contains_key(&key) {
+            ended_events.send(CollisionEnded(contact));
+        }
+    }
+
+    collision_response_data.previous_contacts = contacts;
+}
@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+
+use crate::collision_plugin::aabb::AABB;
+use crate::collision_plugin::polygon_component::WorldPolygon;
+use crate::transform2d::Transform2d;
+
+// Keeps the columns from being flat when the thickness is zero or negative
+const MIN_THICKNESS: f32 = 1e-3;
+
+/// Terrain made of evenly spaced height samples along the local X axis, starting at x = 0.
+/// Everything below the surface is considered solid, down to `thickness` under the lowest sample.
+#[derive(Component, Reflect, Clone, Debug)]
+#[reflect(Component)]
+pub struct HeightfieldComponent
+{
+    pub heights: Vec<f32>,
+    pub cell_width: f32,
+    pub thickness: f32,
+}
+
+impl Default for HeightfieldComponent {
+    fn default() -> Self {
+        HeightfieldComponent {
+            heights: vec!(0f32, 0f32),
+            cell_width: 1f32,
+            thickness: 1f32,
+        }
+    }
+}
+
+impl HeightfieldComponent {
+    pub fn new(heights: Vec<f32>, cell_width: f32) -> Self
+    {
+        assert!(heights.len() >= 2, "Heightfield needs at least 2 samples, got {}", heights.len());
+        assert!(cell_width > 0f32, "Heightfield cell width must be positive, got {}", cell_width);
+
+        HeightfieldComponent {
+            heights,
+            cell_width,
+            thickness: cell_width,
+        }
+    }
+
+    pub fn cell_count(&self) -> usize {
+        return self.heights.len() - 1;
+    }
+
+    pub fn width(&self) -> f32 {
+        return self.cell_count() as f32 * self.cell_width;
+    }
+
+    fn bottom(&self) -> f32 {
+        let min_height = self.heights.iter().cloned().fold(f32::INFINITY, f32::min);
+        return min_height - self.thickness.max(MIN_THICKNESS);
+    }
+
+    /// Range of cells overlapping the local space interval [min_x, max_x]
+    pub fn get_cell_range(&self, min_x: f32, max_x: f32) -> std::ops::Range<usize> {
+        let start = (min_x / self.cell_width).floor().max(0f32) as usize;
+        let end = ((max_x / self.cell_width).ceil().max(0f32) as usize).min(self.cell_count());
+        return start.min(end)..end;
+    }
+
+    /// Range of cells whose columns may touch the given world space bounding box
+    pub fn get_cell_range_in_aabb(&self, transform: &Transform2d, min: Vec2, max: Vec2) -> std::ops::Range<usize> {
+        let corners = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+        let (local_min_x, local_max_x) = corners.iter()
+            .map(|&corner| transform.inv_transform_point(corner).x / transform.scale)
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), x| (lo.min(x), hi.max(x)));
+
+        return self.get_cell_range(local_min_x, local_max_x);
+    }
+
+    /// Convex column below the surface of a single cell, in world space.
+    /// Built from the heights directly, the column is convex and counter-clockwise by construction
+    pub fn get_cell_world_polygon(&self, cell: usize, transform: &Transform2d) -> WorldPolygon {
+        let x0 = cell as f32 * self.cell_width;
+        let x1 = x0 + self.cell_width;
+        let bottom = self.bottom();
+
+        return WorldPolygon::from_points(vec!(
+            transform.transform_point(Vec2::new(x0, bottom)),
+            transform.transform_point(Vec2::new(x1, bottom)),
+            transform.transform_point(Vec2::new(x1, self.heights[cell + 1])),
+            transform.transform_point(Vec2::new(x0, self.heights[cell])),
+        ), 0f32);
+    }
+
+    /// Surface line of the heightfield, in world space
+    pub fn get_surface_points(&self, transform: &Transform2d) -> Vec<Vec2> {
+        return self.heights.iter()
+            .enumerate()
+            .map(|(i, &h)| transform.transform_point(Vec2::new(i as f32 * self.cell_width, h)))
+            .collect();
+    }
+
+    pub fn get_aabb(&self, transform: &Transform2d) -> AABB {
+        let max_height = self.heights.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let bottom = self.bottom();
+        let width = self.width();
+
+        let corners = vec!(
+            Vec2::new(0f32, bottom),
+            Vec2::new(width, bottom),
+            Vec2::new(width, max_height),
+            Vec2::new(0f32, max_height),
+        );
+
+        // Same convention as polygons: the AABB is relative to the translation
+        let mut bounding_box = AABB::from(&corners.iter().map(|&p| transform.rotate(p * transform.scale)).collect());
+        bounding_box.refit(transform, 0f32, Vec2::ZERO);
+        return bounding_box;
+    }
+}
@@ -1,22 +1,22 @@
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
 
-use crate::collision_plugin::polygon_component::PolygonComponent;
-use crate::transform2d::Transform2d;
+use crate::collision_plugin::polygon_component::WorldPolygon;
 
-pub fn get_projection(polygon: &PolygonComponent, transform: &Transform2d, axis: Vec2) -> Vec2
+pub fn get_projection(polygon: &WorldPolygon, axis: Vec2) -> Vec2
 {
-    let min = polygon.get_transformed_points(transform).iter()
-        .map(move |&p| p.dot(axis))
-        .min_by(move |lhs, rhs| lhs.total_cmp(rhs))
-        .unwrap();
+    let mut min = f32::INFINITY;
+    let mut max = f32::NEG_INFINITY;
+    for point in &polygon.points {
+        let projection = point.dot(axis);
+        min = min.min(projection);
+        max = max.max(projection);
+    }
 
-    let max = polygon.get_transformed_points(transform).iter()
-        .map(move |&p| p.dot(axis))
-        .max_by(move |lhs, rhs| lhs.total_cmp(rhs))
-        .unwrap();
+    // Rounded polygons extend by their radius in every direction
+    let margin = polygon.radius * axis.length();
 
-    return Vec2::new(min, max);
+    return Vec2::new(min - margin, max + margin);
 }
 
 pub fn overlaps(lhs: Vec2, rhs: Vec2) -> bool
@@ -36,29 +36,6 @@ pub fn triple_product(a: &Vec2, b: &Vec2, c: &Vec2) -> Vec2
     return sc.xy();
 }
 
-pub fn find_furthest_point(polygon: &PolygonComponent, transform: &Transform2d, direction: Vec2) -> Vec2
-{
-    return polygon.get_transformed_points(transform).iter()
-        .max_by(move |lhs, rhs| {
-            let lhs_dot = lhs.dot(direction);
-            let rhs_dot = rhs.dot(direction);
-            return lhs_dot.total_cmp(&rhs_dot);
-        })
-        .unwrap().clone();
-}
-
-pub fn find_n_furthest_point(n: usize, polygon: &PolygonComponent, transform: &Transform2d, direction: Vec2) -> Vec<Vec2>
-{
-    let mut points = polygon.get_transformed_points(transform);
-    points.sort_by(move |lhs, rhs| {
-        let lhs_dot = lhs.dot(direction);
-        let rhs_dot = rhs.dot(direction);
-        return rhs_dot.total_cmp(&lhs_dot);
-    });
-
-    return points[0..n].to_vec();
-}
-
 pub trait Cross {
     fn cross_float(self, rhs: f32) -> Vec2;
     fn cross_vec(self, rhs: Self) -> f32;
@@ -12,9 +12,15 @@ pub mod plugin;
 pub mod rendering;
 pub mod aabb;
 pub mod polygon_component;
+pub mod heightfield_component;
+pub mod collision_layers;
+pub mod events;
+pub mod spatial_query;
+pub mod ccd;
+pub mod one_way_platform;
 pub mod polygon_plugin;
 mod broad_phase;
-mod narrow_phase;
+pub mod narrow_phase;
 mod helpers;
 mod collision_response;
 mod debug;
@@ -22,6 +28,14 @@ mod debug;
 #[derive(Default, Component)]
 pub struct PhysicsAwake;
 
+/// Collider that only detects overlaps: its contacts are reported by the collision events and it is found
+/// by the spatial queries, but the collision response never pushes it or the entities it touches
+#[derive(Default, Component, Clone, Copy, Debug)]
+pub struct Sensor;
+
+/// Entities having a shape taking part in the collision pipeline
+pub type ColliderFilter = Or<(With<polygon_component::PolygonComponent>, With<heightfield_component::HeightfieldComponent>)>;
+
 //FIXME: TIMESTEP is not implemented as it currently breaks ordering
 const TIMESTEP: f64 = 1.0 /60.0;
 
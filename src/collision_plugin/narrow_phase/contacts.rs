@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+
+use crate::collision_plugin::polygon_component::WorldPolygon;
+
+// Origin of a contact point, stored in its feature ID
+const FEATURE_INCIDENT_START: u32 = 0;
+const FEATURE_INCIDENT_END: u32 = 1;
+const FEATURE_CLIP_START: u32 = 2;
+const FEATURE_CLIP_END: u32 = 3;
+
+// Bias toward p1's edge as reference, so nearly parallel edges don't swap roles from one step to the next
+const REFERENCE_EDGE_TOLERANCE: f32 = 0.001;
+
+/// One or two contact points with their own penetration depth.
+/// Feature IDs only depend on the edges and vertices which made each point,
+/// so they stay the same between steps while the polygons touch in the same way.
+pub(crate) struct ContactManifold {
+    pub points: Vec<Vec2>,
+    pub depths: Vec<f32>,
+    pub feature_ids: Vec<u32>,
+}
+
+struct Edge {
+    index: usize,
+    start: Vec2,
+    end: Vec2,
+    // Outward normal, polygons are counter clockwise
+    normal: Vec2,
+}
+
+#[derive(Clone, Copy)]
+struct ClipPoint {
+    position: Vec2,
+    feature: u32,
+}
+
+// Reference edge, incident edge, origin of the point on the incident edge, and which polygon holds the reference edge
+fn make_feature_id(reference_edge: usize, incident_edge: usize, feature: u32, flipped: bool) -> u32 {
+    return ((reference_edge as u32 & 0xFF) << 24)
+        | ((incident_edge as u32 & 0xFF) << 16)
+        | (feature << 8)
+        | flipped as u32;
+}
+
+// Among the two edges sharing the furthest vertex along `direction`, the one most perpendicular to it
+fn find_best_edge(polygon: &WorldPolygon, direction: Vec2) -> Edge {
+    let points = &polygon.points;
+    let count = points.len();
+    let furthest = polygon.find_furthest_index(direction);
+
+    let previous = (furthest + count - 1) % count;
+    let next = (furthest + 1) % count;
+
+    let to_previous = (points[furthest] - points[previous]).normalize_or_zero();
+    let to_next = (points[furthest] - points[next]).normalize_or_zero();
+
+    let (index, start, end) = if to_next.dot(direction) <= to_previous.dot(direction) {
+        (furthest, points[furthest], points[next])
+    } else {
+        (previous, points[previous], points[furthest])
+    };
+
+    return Edge {
+        index,
+        start,
+        end,
+        normal: polygon.normals[index],
+    };
+}
+
+// Sutherland-Hodgman against a single plane, keeping the points for which `direction.dot(p) >= offset`
+fn clip(points: &[ClipPoint], direction: Vec2, offset: f32, clip_feature: u32) -> Vec<ClipPoint> {
+    let mut clipped = vec!();
+    let distance_0 = direction.dot(points[0].position) - offset;
+    let distance_1 = direction.dot(points[1].position) - offset;
+
+    if distance_0 >= 0f32 {
+        clipped.push(points[0]);
+    }
+    if distance_1 >= 0f32 {
+        clipped.push(points[1]);
+    }
+
+    if distance_0 * distance_1 < 0f32 {
+        let ratio = distance_0 / (distance_0 - distance_1);
+        clipped.push(ClipPoint {
+            position: points[0].position + (points[1].position - points[0].position) * ratio,
+            feature: clip_feature,
+        });
+    }
+
+    return clipped;
+}
+
+/// Contact manifold of two overlapping polygons, `direction` going from p1 toward p2.
+/// The reference edge is the edge most perpendicular to the direction, the incident edge of the
+/// other polygon is clipped by its sides, and only the points behind the reference edge are kept.
+pub(crate) fn get_contact_manifold(p1: &WorldPolygon, p2: &WorldPolygon, direction: Vec2) -> ContactManifold {
+    let direction = direction.normalize_or_zero();
+    let radius_1 = p1.radius;
+    let radius_2 = p2.radius;
+
+    let edge_1 = find_best_edge(p1, direction);
+    let edge_2 = find_best_edge(p2, -direction);
+
+    let flipped = (edge_2.end - edge_2.start).normalize_or_zero().dot(direction).abs() + REFERENCE_EDGE_TOLERANCE
+        < (edge_1.end - edge_1.start).normalize_or_zero().dot(direction).abs();
+    let (reference, incident, incident_radius) = if flipped {
+        (&edge_2, &edge_1, radius_1)
+    } else {
+        (&edge_1, &edge_2, radius_2)
+    };
+
+    let reference_direction = (reference.end - reference.start).normalize_or_zero();
+    let incident_points = [
+        ClipPoint { position: incident.start, feature: FEATURE_INCIDENT_START },
+        ClipPoint { position: incident.end, feature: FEATURE_INCIDENT_END },
+    ];
+
+    let mut clipped = clip(&incident_points, reference_direction, reference_direction.dot(reference.start), FEATURE_CLIP_START);
+    if clipped.len() == 2 {
+        clipped = clip(&clipped, -reference_direction, -reference_direction.dot(reference.end), FEATURE_CLIP_END);
+    }
+
+    // Rounded polygons touch before their cores do
+    let face_offset = reference.normal.dot(reference.start) + radius_1 + radius_2;
+
+    let mut manifold = ContactManifold {
+        points: vec!(),
+        depths: vec!(),
+        feature_ids: vec!(),
+    };
+
+    if clipped.len() == 2 {
+        for point in clipped {
+            let depth = face_offset - reference.normal.dot(point.position);
+            if depth < 0f32 {
+                continue;
+            }
+
+            // Move the point on the rounded surface of the incident polygon
+            manifold.points.push(point.position - reference.normal * incident_radius);
+            manifold.depths.push(depth);
+            manifold.feature_ids.push(make_feature_id(reference.index, incident.index, point.feature, flipped));
+        }
+    }
+
+    // Edges nearly parallel to the direction, or numerical issues, fall back to the deepest point of p1
+    if manifold.points.is_empty() {
+        let point = p1.find_furthest_point(direction);
+        manifold.points.push(point);
+        manifold.depths.push((direction.dot(point) - direction.dot(p2.find_furthest_point(-direction))).max(0f32));
+        manifold.feature_ids.push(make_feature_id(edge_1.index, edge_2.index, FEATURE_INCIDENT_START, false));
+    }
+
+    return manifold;
+}
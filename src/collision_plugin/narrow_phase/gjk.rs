@@ -1,31 +1,78 @@
 use bevy::prelude::*;
 
 use crate::collision_plugin::data_structs::CollisionInfo;
-use crate::collision_plugin::helpers::{find_furthest_point, find_n_furthest_point, triple_product};
-use crate::collision_plugin::polygon_component::PolygonComponent;
-use crate::transform2d::Transform2d;
-
-pub fn get_support(p1: &PolygonComponent, t1: &Transform2d,
-               p2: &PolygonComponent, t2: &Transform2d,
-               direction: Vec2,
-) -> Vec2 {
-    return find_furthest_point(p1, t1, direction)
-        - find_furthest_point(p2, t2, -direction);
+use crate::collision_plugin::helpers::triple_product;
+use crate::collision_plugin::narrow_phase::contacts::get_contact_manifold;
+use crate::collision_plugin::polygon_component::WorldPolygon;
+
+const DISTANCE_TOLERANCE: f32 = 0.0001f32;
+
+/// Support point of the Minkowski difference of the core shapes, the rounded skins are handled by the callers
+pub fn get_support(p1: &WorldPolygon, p2: &WorldPolygon, direction: Vec2) -> Vec2 {
+    return p1.find_furthest_vertex(direction)
+        - p2.find_furthest_vertex(-direction);
+}
+
+/// How two polygons touch, found by [`find_contact`]
+#[derive(Clone, Copy, Debug)]
+pub enum GjkContact {
+    /// The core shapes overlap, EPA expands the simplex to find the normal and depth
+    Core([Vec2; 3]),
+    /// Only the rounded skins overlap, the normal pushes p1 out of p2
+    Skin { normal: Vec2, depth: f32 },
+}
+
+/// Sharp polygons only run the boolean GJK. Rounded polygons compare the distance between their core shapes
+/// with the sum of their radii, and fall back to GJK when the core shapes overlap.
+pub fn find_contact(p1: &WorldPolygon, p2: &WorldPolygon) -> Option<GjkContact> {
+    let radius = p1.radius + p2.radius;
+    if radius > 0f32 {
+        if let Some(core) = get_core_distance(p1, p2) {
+            let depth = radius - core.distance;
+            if depth <= 0f32 {
+                return None;
+            }
+            return Some(GjkContact::Skin { normal: -core.normal, depth });
+        }
+    }
+
+    let (collided, simplex) = check_collision(p1, p2);
+    if !collided {
+        return None;
+    }
+    return Some(GjkContact::Core(simplex));
+}
+
+pub fn get_contact_info(p1: &WorldPolygon, p2: &WorldPolygon, contact: GjkContact) -> CollisionInfo {
+    match contact {
+        GjkContact::Core(simplex) => return get_info_collisions(p1, p2, simplex),
+        GjkContact::Skin { normal, depth } => {
+            let manifold = get_contact_manifold(p1, p2, -normal);
+            return CollisionInfo {
+                collision_pair: None,
+                location: manifold.points,
+                depths: manifold.depths,
+                feature_ids: manifold.feature_ids,
+                normal,
+                distance: depth,
+            };
+        }
+    }
 }
 
-pub fn check_collision(p1: &PolygonComponent, t1: &Transform2d,
-                       p2: &PolygonComponent, t2: &Transform2d) -> (bool, [Vec2; 3])
+/// Boolean GJK on the core shapes, the rounded skins are ignored
+pub fn check_collision(p1: &WorldPolygon, p2: &WorldPolygon) -> (bool, [Vec2; 3])
 {
     let mut simplex = [Vec2::ZERO; 3];
     let mut index = 0;
 
-    let mut a = get_support(p1, t1, p2, t2, Vec2::X);
+    let mut a = get_support(p1, p2, Vec2::X);
     simplex[0] = a;
 
     let mut direction = -a;
 
     for _ in 0..32 {
-        a = get_support(p1, t1, p2, t2, direction);
+        a = get_support(p1, p2, direction);
 
         index += 1;
         simplex[index] = a;
@@ -78,8 +125,8 @@ pub fn check_collision(p1: &PolygonComponent, t1: &Transform2d,
     return (false, [Vec2::ZERO; 3]);
 }
 
-pub fn get_info_collisions(p1: &PolygonComponent, t1: &Transform2d,
-                           p2: &PolygonComponent, t2: &Transform2d,
+/// EPA on the core shapes, the radii are added to the depth found
+pub fn get_info_collisions(p1: &WorldPolygon, p2: &WorldPolygon,
                            simplex: [Vec2; 3],
 ) -> CollisionInfo {
     const MAX_POLYTOPE_VERTICES: usize = 256;
@@ -114,7 +161,7 @@ pub fn get_info_collisions(p1: &PolygonComponent, t1: &Transform2d,
             }
         }
 
-        let support = get_support(p1, t1, p2, t2, min_normal);
+        let support = get_support(p1, p2, min_normal);
         let dist = min_normal.dot(support);
         if polytope.len() > MAX_POLYTOPE_VERTICES {
             break;
@@ -126,41 +173,165 @@ pub fn get_info_collisions(p1: &PolygonComponent, t1: &Transform2d,
         }
     }
 
-    const N_POINTS : usize = 2;
+    let manifold = get_contact_manifold(p1, p2, min_normal);
 
-    let mut location1 = find_n_furthest_point(N_POINTS, p1, t1, min_normal);
-    let mut location2 = find_n_furthest_point(N_POINTS, p2, t2, -min_normal);
+    return CollisionInfo {
+        collision_pair: None,
+        location: manifold.points,
+        depths: manifold.depths,
+        feature_ids: manifold.feature_ids,
+        normal: -min_normal,
+        distance: min_dist + TOLERANCE * 2f32 + p1.radius + p2.radius,
+    };
+}
 
-    let mut location = Vec::new();
-    location.append(&mut location1);
-    location.append(&mut location2);
-    location.sort_by(move |lhs, rhs| {
-        let lhs_dot = lhs.dot(min_normal);
-        let rhs_dot = rhs.dot(min_normal);
-        return rhs_dot.total_cmp(&lhs_dot);
-    });
-    // location.dedup();
+/// Result of a distance query between two separated polygons
+#[derive(Clone, Copy, Debug)]
+pub struct DistanceInfo {
+    /// Gap between the surfaces of the polygons, rounded polygons included
+    pub distance: f32,
+    /// Closest point on the surface of the first polygon
+    pub point_a: Vec2,
+    /// Closest point on the surface of the second polygon
+    pub point_b: Vec2,
+    /// Unit direction from point_a toward point_b
+    pub normal: Vec2,
+}
 
-    location = location[0..N_POINTS].to_vec();
+// Vertex of the Minkowski difference, remembering the vertices of each polygon it comes from
+#[derive(Clone, Copy)]
+struct SupportPoint {
+    point: Vec2,
+    point_a: Vec2,
+    point_b: Vec2,
+}
 
+// Support of the core polygons, the radius is added once their distance is known
+fn get_core_support(p1: &WorldPolygon, p2: &WorldPolygon, direction: Vec2) -> SupportPoint {
+    let point_a = p1.find_furthest_vertex(direction);
+    let point_b = p2.find_furthest_vertex(-direction);
+    return SupportPoint {
+        point: point_a - point_b,
+        point_a,
+        point_b,
+    };
+}
 
-    // let location = vec!(
-    //     find_furthest_point(p1, t1, min_normal),
-    //     find_furthest_point(p2, t2, min_normal),
-    // );
+// Closest point to the origin on the segment, with the barycentric weights of its ends
+fn closest_on_segment(a: Vec2, b: Vec2) -> (Vec2, f32, f32) {
+    let ab = b - a;
+    let length_squared = ab.length_squared();
+    if length_squared <= f32::EPSILON {
+        return (a, 1f32, 0f32);
+    }
 
-    assert!(location.len() <= N_POINTS);
-    // let mut location = location1;
-    // if !p2.is_point_inside(t2, &location[0])
-    // {
-    //     location = location2;
-    //     // min_normal *= -1f32;
-    // }
+    let t = (-a.dot(ab) / length_squared).clamp(0f32, 1f32);
+    return (a + ab * t, 1f32 - t, t);
+}
 
-    return CollisionInfo {
-        collision_pair: None,
-        location: location,
-        normal: -min_normal,
-        distance: min_dist + TOLERANCE * 2f32,
-    };
-}
\ No newline at end of file
+// Reduce the simplex to the feature closest to the origin, None if it contains the origin
+fn reduce_simplex(simplex: &mut Vec<SupportPoint>, weights: &mut Vec<f32>) -> Option<Vec2> {
+    match simplex.len() {
+        1 => {
+            *weights = vec!(1f32);
+            return Some(simplex[0].point);
+        }
+        2 => {
+            let (closest, w0, w1) = closest_on_segment(simplex[0].point, simplex[1].point);
+            *weights = vec!(w0, w1);
+            return Some(closest);
+        }
+        _ => {
+            let (a, b, c) = (simplex[0].point, simplex[1].point, simplex[2].point);
+            let area = (b - a).perp_dot(c - a);
+            let inside = area != 0f32
+                && (b - a).perp_dot(-a) * area >= 0f32
+                && (c - b).perp_dot(-b) * area >= 0f32
+                && (a - c).perp_dot(-c) * area >= 0f32;
+            if inside {
+                return None;
+            }
+
+            let mut best = (f32::INFINITY, Vec2::ZERO, 0, 0, 0f32, 0f32);
+            for (i, j) in [(0, 1), (1, 2), (2, 0)] {
+                let (closest, wi, wj) = closest_on_segment(simplex[i].point, simplex[j].point);
+                if closest.length_squared() < best.0 {
+                    best = (closest.length_squared(), closest, i, j, wi, wj);
+                }
+            }
+
+            let (_, closest, i, j, wi, wj) = best;
+            *simplex = vec!(simplex[i], simplex[j]);
+            *weights = vec!(wi, wj);
+            return Some(closest);
+        }
+    }
+}
+
+/// Distance and closest points between the core shapes of two polygons, their radii ignored.
+/// None if the core shapes overlap or touch.
+/// GJK keeping the simplex feature closest to the origin at each iteration.
+pub fn get_core_distance(p1: &WorldPolygon, p2: &WorldPolygon) -> Option<DistanceInfo> {
+    const MAX_ITERATIONS: usize = 32;
+
+    let mut direction = p2.points[0] - p1.points[0];
+    if direction.length_squared() == 0f32 {
+        direction = Vec2::X;
+    }
+
+    let mut simplex = vec!(get_core_support(p1, p2, -direction));
+    let mut weights = vec!(1f32);
+    let mut closest = simplex[0].point;
+
+    for _ in 0..MAX_ITERATIONS {
+        closest = reduce_simplex(&mut simplex, &mut weights)?;
+
+        let distance = closest.length();
+        if distance <= DISTANCE_TOLERANCE {
+            return None;
+        }
+
+        let support = get_core_support(p1, p2, -closest);
+
+        // No support point gets closer to the origin, the closest feature is found
+        let progress = distance - support.point.dot(closest) / distance;
+        if progress <= DISTANCE_TOLERANCE * distance.max(1f32)
+            || simplex.iter().any(|vertex| vertex.point.distance_squared(support.point) <= f32::EPSILON) {
+            break;
+        }
+
+        simplex.push(support);
+    }
+
+    let mut point_a = Vec2::ZERO;
+    let mut point_b = Vec2::ZERO;
+    for (vertex, weight) in simplex.iter().zip(&weights) {
+        point_a += vertex.point_a * *weight;
+        point_b += vertex.point_b * *weight;
+    }
+
+    return Some(DistanceInfo {
+        distance: closest.length(),
+        point_a,
+        point_b,
+        normal: -closest.normalize(),
+    });
+}
+
+/// Distance and closest points between two polygons, None if they overlap or touch.
+/// The rounded skins are removed from the distance between the core shapes.
+pub fn get_distance(p1: &WorldPolygon, p2: &WorldPolygon) -> Option<DistanceInfo> {
+    let core = get_core_distance(p1, p2)?;
+
+    let distance = core.distance - p1.radius - p2.radius;
+    if distance <= 0f32 {
+        return None;
+    }
+
+    return Some(DistanceInfo {
+        distance,
+        point_a: core.point_a + core.normal * p1.radius,
+        point_b: core.point_b - core.normal * p2.radius,
+        normal: core.normal,
+    });
+}
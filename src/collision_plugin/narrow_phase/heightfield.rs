@@ -0,0 +1,37 @@
+use crate::collision_plugin::data_structs::{CollisionInfo, CollisionPair};
+use crate::collision_plugin::heightfield_component::HeightfieldComponent;
+use crate::collision_plugin::narrow_phase::gjk;
+use crate::collision_plugin::polygon_component::WorldPolygon;
+use crate::transform2d::Transform2d;
+
+/// Collide a polygon against the cells of a heightfield it overlaps. Only the cells under the
+/// polygon's bounding box are tested, each of them being a small convex column.
+/// One CollisionInfo is returned per colliding cell, ordered as the given pair.
+pub(crate) fn check_collision(pair: &CollisionPair,
+                              heightfield: &HeightfieldComponent, t_heightfield: &Transform2d,
+                              polygon: &WorldPolygon,
+                              heightfield_is_a: bool,
+                              compute_collision_infos: bool,
+) -> Vec<CollisionInfo>
+{
+    let (min, max) = polygon.get_bounds();
+
+    let mut collision_infos = vec!();
+    for cell in heightfield.get_cell_range_in_aabb(t_heightfield, min, max) {
+        let cell_polygon = heightfield.get_cell_world_polygon(cell, t_heightfield);
+
+        let (p1, p2) = if heightfield_is_a {
+            (&cell_polygon, polygon)
+        } else {
+            (polygon, &cell_polygon)
+        };
+
+        if let Some(contact) = gjk::find_contact(p1, p2) {
+            let mut collision_info = if compute_collision_infos { gjk::get_contact_info(p1, p2, contact) } else { CollisionInfo::default() };
+            collision_info.collision_pair = Some(pair.clone());
+            collision_infos.push(collision_info);
+        }
+    }
+
+    return collision_infos;
+}
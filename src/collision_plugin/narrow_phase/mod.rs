@@ -1,3 +1,11 @@
 pub mod systems;
-mod gjk;
+pub mod gjk;
 mod sat;
+mod mpr;
+mod heightfield;
+mod contacts;
+pub(crate) mod raycast;
+pub(crate) mod time_of_impact;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,131 @@
+use bevy::prelude::*;
+
+use crate::collision_plugin::data_structs::CollisionInfo;
+use crate::collision_plugin::narrow_phase::contacts::get_contact_manifold;
+use crate::collision_plugin::narrow_phase::gjk::{self, get_support};
+use crate::collision_plugin::polygon_component::WorldPolygon;
+
+const MAX_ITERATIONS: usize = 32;
+const TOLERANCE: f32 = 0.0001f32;
+
+// Average of the vertices, always inside a convex polygon
+fn get_center(polygon: &WorldPolygon) -> Vec2 {
+    return polygon.points.iter().sum::<Vec2>() / polygon.points.len() as f32;
+}
+
+// Normal of the portal's edge, pointing away from the interior point
+fn get_portal_normal(v0: Vec2, v1: Vec2, v2: Vec2) -> Vec2 {
+    let normal = (v2 - v1).perp().normalize_or_zero();
+    if normal.dot(v1 - v0) < 0f32 {
+        return -normal;
+    }
+    return normal;
+}
+
+/// Minkowski Portal Refinement (XenoCollide) on the Minkowski difference of the core shapes, the radii added to the depth.
+/// A portal is cast from a point inside the difference toward the origin and refined until it reaches its surface.
+/// Returns None if the polygons are separated, else the normal pushing p1 out of p2 and the penetration depth along it.
+/// MPR trades accuracy for speed: the depth and normal are measured along the portal's direction, from the centers of the
+/// polygons, rather than along the shortest way out. Shallow contacts are close enough, but the response over or under
+/// pushes deep overlaps. Use GJK or SAT when the minimum translation matters.
+pub(crate) fn find_penetration(p1: &WorldPolygon, p2: &WorldPolygon) -> Option<(Vec2, f32)> {
+    // Rounded polygons whose core shapes are apart can only touch through their skins
+    let radius = p1.radius + p2.radius;
+    if radius > 0f32 {
+        if let Some(core) = gjk::get_core_distance(p1, p2) {
+            let depth = radius - core.distance;
+            if depth <= 0f32 {
+                return None;
+            }
+            return Some((-core.normal, depth));
+        }
+    }
+
+    let (normal, depth) = find_core_penetration(p1, p2)?;
+    return Some((normal, depth + radius));
+}
+
+// MPR on the core shapes
+fn find_core_penetration(p1: &WorldPolygon, p2: &WorldPolygon) -> Option<(Vec2, f32)> {
+    // Interior point of the Minkowski difference, nudged when the centers match so it has a direction
+    let mut v0 = get_center(p1) - get_center(p2);
+    if v0.length_squared() <= f32::EPSILON {
+        v0 = Vec2::new(TOLERANCE, 0f32);
+    }
+
+    // First vertex of the portal, in the direction of the origin
+    let mut direction = -v0;
+    let mut v1 = get_support(p1, p2, direction);
+    if v1.dot(direction) <= 0f32 {
+        return None;
+    }
+
+    // Second vertex, on the side of the segment v0 v1 where the origin is.
+    // When the origin is on that segment, either side works
+    direction = (v1 - v0).perp();
+    if direction.dot(-v0) < 0f32 {
+        direction = -direction;
+    }
+    let mut v2 = get_support(p1, p2, direction);
+    if v2.dot(direction) <= 0f32 {
+        return None;
+    }
+
+    let mut hit = false;
+    for _ in 0..MAX_ITERATIONS {
+        let normal = get_portal_normal(v0, v1, v2);
+        if normal == Vec2::ZERO {
+            break;
+        }
+
+        // The origin is on the interior side of the portal, so inside the triangle v0 v1 v2
+        if normal.dot(v1) >= 0f32 {
+            hit = true;
+        }
+
+        let v3 = get_support(p1, p2, normal);
+        if !hit && v3.dot(normal) <= 0f32 {
+            return None;
+        }
+
+        // The portal reached the surface of the Minkowski difference
+        if (v3 - v1).dot(normal) <= TOLERANCE
+            || v3.distance_squared(v1) <= f32::EPSILON
+            || v3.distance_squared(v2) <= f32::EPSILON {
+            if !hit {
+                return None;
+            }
+            return Some((-normal, normal.dot(v1)));
+        }
+
+        // Keep the half of the portal crossed by the ray from v0 to the origin
+        let split = v3 - v0;
+        if split.perp_dot(-v0) * split.perp_dot(v1 - v0) >= 0f32 {
+            v2 = v3;
+        } else {
+            v1 = v3;
+        }
+    }
+
+    if !hit {
+        return None;
+    }
+    let normal = get_portal_normal(v0, v1, v2);
+    return Some((-normal, normal.dot(v1)));
+}
+
+pub(crate) fn get_info_collisions(p1: &WorldPolygon, p2: &WorldPolygon,
+                                  normal: Vec2,
+                                  distance: f32,
+) -> CollisionInfo {
+    let manifold = get_contact_manifold(p1, p2, -normal);
+
+    return CollisionInfo {
+        collision_pair: None,
+        location: manifold.points,
+        depths: manifold.depths,
+        feature_ids: manifold.feature_ids,
+        normal,
+        distance,
+    };
+}
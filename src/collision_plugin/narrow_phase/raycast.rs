@@ -0,0 +1,130 @@
+use bevy::prelude::*;
+
+use crate::collision_plugin::heightfield_component::HeightfieldComponent;
+use crate::collision_plugin::polygon_component::WorldPolygon;
+use crate::transform2d::Transform2d;
+
+/// Where a ray `origin + direction * toi` enters a shape, the normal pointing out of the shape.
+/// Rays starting inside a shape hit it at a toi of 0, with a normal facing the ray.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RayIntersection {
+    pub toi: f32,
+    pub normal: Vec2,
+}
+
+fn keep_closest(closest: &mut Option<RayIntersection>, toi: f32, normal: Vec2) {
+    if closest.map_or(true, |intersection| toi < intersection.toi) {
+        *closest = Some(RayIntersection { toi, normal });
+    }
+}
+
+// Clip the ray by the half planes of every edge, the last entering plane is the one hit
+fn cast_ray_core(polygon: &WorldPolygon, origin: Vec2, direction: Vec2, max_toi: f32) -> Option<RayIntersection> {
+    let points = &polygon.points;
+    let mut toi_enter = 0f32;
+    let mut toi_exit = max_toi;
+    let mut normal = Vec2::ZERO;
+
+    for i in 0..points.len() {
+        let edge_normal = polygon.normals[i];
+        if edge_normal == Vec2::ZERO {
+            continue;
+        }
+
+        let distance = edge_normal.dot(points[i] - origin);
+        let speed = edge_normal.dot(direction);
+        if speed == 0f32 {
+            if distance < 0f32 {
+                return None;
+            }
+            continue;
+        }
+
+        let toi = distance / speed;
+        if speed < 0f32 {
+            if toi > toi_enter {
+                toi_enter = toi;
+                normal = edge_normal;
+            }
+        } else {
+            toi_exit = toi_exit.min(toi);
+        }
+
+        if toi_enter > toi_exit {
+            return None;
+        }
+    }
+
+    if normal == Vec2::ZERO {
+        normal = -direction.normalize_or_zero();
+    }
+    return Some(RayIntersection { toi: toi_enter, normal });
+}
+
+// Rounded polygons are made of their edges pushed out by the radius, and a circle on each vertex
+fn cast_ray_rounded(polygon: &WorldPolygon, origin: Vec2, direction: Vec2, max_toi: f32) -> Option<RayIntersection> {
+    let points = &polygon.points;
+    let radius = polygon.radius;
+    let mut closest = None;
+
+    for i in 0..points.len() {
+        let start = points[i];
+        let end = points[(i + 1) % points.len()];
+        let edge = end - start;
+        let edge_normal = polygon.normals[i];
+
+        let speed = edge_normal.dot(direction);
+        if speed < 0f32 {
+            let toi = (edge_normal.dot(start - origin) + radius) / speed;
+            let along = (origin + direction * toi - start).dot(edge) / edge.length_squared();
+            if toi >= 0f32 && toi <= max_toi && (0f32..=1f32).contains(&along) {
+                keep_closest(&mut closest, toi, edge_normal);
+            }
+        }
+
+        // Smallest root of |origin + direction * t - start|² = radius²
+        let offset = origin - start;
+        let a = direction.length_squared();
+        let b = offset.dot(direction);
+        let c = offset.length_squared() - radius * radius;
+        let discriminant = b * b - a * c;
+        if a > 0f32 && discriminant >= 0f32 {
+            let toi = (-b - discriminant.sqrt()) / a;
+            if toi >= 0f32 && toi <= max_toi {
+                keep_closest(&mut closest, toi, (offset + direction * toi).normalize_or_zero());
+            }
+        }
+    }
+
+    return closest;
+}
+
+pub(crate) fn cast_ray_polygon(polygon: &WorldPolygon,
+                               origin: Vec2, direction: Vec2, max_toi: f32,
+) -> Option<RayIntersection> {
+    if polygon.radius <= 0f32 {
+        return cast_ray_core(polygon, origin, direction, max_toi);
+    }
+
+    if polygon.is_point_inside(origin) {
+        return Some(RayIntersection { toi: 0f32, normal: -direction.normalize_or_zero() });
+    }
+    return cast_ray_rounded(polygon, origin, direction, max_toi);
+}
+
+/// Only the cells under the ray's bounding box are tested
+pub(crate) fn cast_ray_heightfield(heightfield: &HeightfieldComponent, transform: &Transform2d,
+                                   origin: Vec2, direction: Vec2, max_toi: f32,
+) -> Option<RayIntersection> {
+    let end = origin + direction * max_toi;
+
+    let mut closest: Option<RayIntersection> = None;
+    for cell in heightfield.get_cell_range_in_aabb(transform, origin.min(end), origin.max(end)) {
+        let cell_polygon = heightfield.get_cell_world_polygon(cell, transform);
+        if let Some(intersection) = cast_ray_polygon(&cell_polygon, origin, direction, max_toi) {
+            keep_closest(&mut closest, intersection.toi, intersection.normal);
+        }
+    }
+
+    return closest;
+}
@@ -1,40 +1,66 @@
 use bevy::prelude::*;
 
+use crate::collision_plugin::data_structs::CollisionInfo;
 use crate::collision_plugin::helpers::{get_projection, overlaps};
-use crate::collision_plugin::polygon_component::PolygonComponent;
-use crate::transform2d::Transform2d;
+use crate::collision_plugin::narrow_phase::contacts::get_contact_manifold;
+use crate::collision_plugin::polygon_component::WorldPolygon;
 
-fn get_sat_axes_to_test(polygon: &PolygonComponent, transform: &Transform2d) -> Vec<Vec2>
+// Direction between the closest vertices of the polygons. Rounded corners are only separated along this axis
+// when the closest features of the core shapes are two vertices
+fn get_vertex_axis(p1: &WorldPolygon, p2: &WorldPolygon) -> Option<Vec2>
 {
-    let mut axes = Vec::with_capacity(polygon.points.len());
-
-    for p in polygon.get_transformed_points(transform).windows(2) {
-        let edge = p[0] - p[1];
-        axes.push(edge.perp());
+    if p1.radius + p2.radius <= 0f32 {
+        return None;
     }
 
-    return axes;
+    let (a, b) = p1.points.iter()
+        .flat_map(|&a| p2.points.iter().map(move |&b| (a, b)))
+        .min_by(|(a1, b1), (a2, b2)| a1.distance_squared(*b1).total_cmp(&a2.distance_squared(*b2)))?;
+    return Some((a - b).normalize_or_zero());
 }
 
-pub(crate) fn check_collision(p1: &PolygonComponent, t1: &Transform2d,
-                              p2: &PolygonComponent, t2: &Transform2d) -> bool {
-    let axes_p1 = get_sat_axes_to_test(p1, t1);
-    for axis in axes_p1 {
-        let p1 = get_projection(p1, t1, axis);
-        let p2 = get_projection(p2, t2, axis);
+// Edge normals of both polygons, read from their cache, and the vertex axis of rounded polygons
+fn get_sat_axes_to_test<'a>(p1: &'a WorldPolygon, p2: &'a WorldPolygon) -> impl Iterator<Item = Vec2> + 'a
+{
+    return p1.normals.iter().chain(p2.normals.iter())
+        .cloned()
+        .chain(get_vertex_axis(p1, p2))
+        .filter(|&axis| axis != Vec2::ZERO);
+}
 
-        if !overlaps(p1, p2)
-        {
-            return false;
+/// Test every edge normal of both polygons, keeping the one with the smallest overlap.
+/// Returns None if an axis separates the polygons, else the normal pushing p1 out of p2 and the penetration depth.
+pub(crate) fn find_min_overlap(p1: &WorldPolygon, p2: &WorldPolygon) -> Option<(Vec2, f32)> {
+    let mut min_overlap = f32::INFINITY;
+    let mut min_normal = Vec2::ZERO;
+
+    for axis in get_sat_axes_to_test(p1, p2) {
+        let projection_1 = get_projection(p1, axis);
+        let projection_2 = get_projection(p2, axis);
+
+        // Distance to move p1 along -axis or +axis to separate them, one projection may contain the other
+        let overlap_negative = projection_1.y - projection_2.x;
+        let overlap_positive = projection_2.y - projection_1.x;
+        if overlap_negative <= 0f32 || overlap_positive <= 0f32 {
+            return None;
+        }
+
+        if overlap_negative < min_overlap {
+            min_overlap = overlap_negative;
+            min_normal = -axis;
+        }
+        if overlap_positive < min_overlap {
+            min_overlap = overlap_positive;
+            min_normal = axis;
         }
     }
 
-    let axes_poly2 = get_sat_axes_to_test(p2, t2);
-    for axis in axes_poly2 {
-        let p1 = get_projection(p1, t1, axis);
-        let p2 = get_projection(p2, t2, axis);
+    return Some((min_normal, min_overlap));
+}
 
-        if !overlaps(p1, p2)
+pub(crate) fn check_collision(p1: &WorldPolygon, p2: &WorldPolygon) -> bool {
+    for axis in get_sat_axes_to_test(p1, p2) {
+        if !overlaps(get_projection(p1, axis), get_projection(p2, axis))
         {
             return false;
         }
@@ -43,3 +69,18 @@ pub(crate) fn check_collision(p1: &PolygonComponent, t1: &Transform2d,
     return true;
 }
 
+pub(crate) fn get_info_collisions(p1: &WorldPolygon, p2: &WorldPolygon,
+                                  normal: Vec2,
+                                  distance: f32,
+) -> CollisionInfo {
+    let manifold = get_contact_manifold(p1, p2, -normal);
+
+    return CollisionInfo {
+        collision_pair: None,
+        location: manifold.points,
+        depths: manifold.depths,
+        feature_ids: manifold.feature_ids,
+        normal,
+        distance,
+    };
+}
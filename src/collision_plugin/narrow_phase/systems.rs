@@ -6,20 +6,24 @@ use rayon::prelude::*;
 use crate::collision_plugin::narrow_phase::sat;
 use crate::collision_plugin::config::{CollisionConfig, NarrowPhaseType};
 use crate::collision_plugin::data_structs::{BroadPhaseData, CollisionInfo, NarrowPhaseData};
-use crate::collision_plugin::narrow_phase::gjk;
-use crate::collision_plugin::polygon_component::PolygonComponent;
+use crate::collision_plugin::heightfield_component::HeightfieldComponent;
+use crate::collision_plugin::narrow_phase::{gjk, heightfield, mpr};
+use crate::collision_plugin::polygon_component::WorldPolygon;
 use crate::transform2d::Transform2d;
 
-pub type NarrowPhaseQuery<'w, 's> = Query<
+pub type NarrowPhaseQuery<'w, 's> = Query<'w, 's, &'static WorldPolygon>;
+
+pub type NarrowPhaseHeightfieldQuery<'w, 's> = Query<
     'w, 's,
     (
-        &'static mut PolygonComponent,
+        &'static HeightfieldComponent,
         &'static Transform2d
     )
 >;
 
 pub(crate) fn narrow_phase(
     query: NarrowPhaseQuery,
+    heightfield_query: NarrowPhaseHeightfieldQuery,
     mut narrow_phase_data: ResMut<NarrowPhaseData>,
     broad_phase_data: Res<BroadPhaseData>,
     config: Res<CollisionConfig>,
@@ -30,21 +34,25 @@ pub(crate) fn narrow_phase(
     narrow_phase_data.collision_infos.clear();
     let _span = info_span!("narrow_phase", name = "dispatching").entered();
 
-    let collision_infos = if config.phase_config.multithread_narrow_phase {
+    let mut collision_infos = if config.phase_config.multithread_narrow_phase {
         match config.phase_config.narrow_phase_type {
             NarrowPhaseType::Disabled => vec!(),
             NarrowPhaseType::SAT => narrow_phase_sat_mt(&broad_phase_data, &query, config.phase_config.compute_info_collision),
             NarrowPhaseType::GJK => narrow_phase_gjk_mt(&broad_phase_data, &query, config.phase_config.compute_info_collision),
+            NarrowPhaseType::MPR => narrow_phase_mpr_mt(&broad_phase_data, &query, config.phase_config.compute_info_collision),
         }
     } else {
         match config.phase_config.narrow_phase_type {
             NarrowPhaseType::Disabled => vec!(),
             NarrowPhaseType::SAT => narrow_phase_sat_st(&broad_phase_data, &query, config.phase_config.compute_info_collision),
             NarrowPhaseType::GJK => narrow_phase_gjk_st(&broad_phase_data, &query, config.phase_config.compute_info_collision),
+            NarrowPhaseType::MPR => narrow_phase_mpr_st(&broad_phase_data, &query, config.phase_config.compute_info_collision),
         }
     };
 
-
+    if !matches!(config.phase_config.narrow_phase_type, NarrowPhaseType::Disabled) {
+        collision_infos.append(&mut narrow_phase_heightfield(&broad_phase_data, &query, &heightfield_query, config.phase_config.compute_info_collision));
+    }
 
     narrow_phase_data.collided_entities.clear();
     for info in &collision_infos {
@@ -60,21 +68,20 @@ pub(crate) fn narrow_phase(
 
 pub(crate) fn narrow_phase_sat_st(broad_phase_data: &BroadPhaseData,
                                   query: &NarrowPhaseQuery,
-                                  _compute_collision_infos: bool,
+                                  compute_collision_infos: bool,
 ) -> Vec<CollisionInfo>
 {
     let _span = info_span!("narrow_phase", name = "SAT").entered();
 
     let collision_infos = broad_phase_data.collision_pairs.iter().filter_map(
         |pair| {
-            let (p1, t1) = query.get(pair.entity_a).unwrap();
-            let (p2, t2) = query.get(pair.entity_b).unwrap();
-            let collided = sat::check_collision(&p1, &t1, &p2, &t2);
+            let Ok(p1) = query.get(pair.entity_a) else { return None; };
+            let Ok(p2) = query.get(pair.entity_b) else { return None; };
+            let Some((normal, distance)) = sat::find_min_overlap(p1, p2) else { return None; };
 
-            if collided == true {
-                return Some(CollisionInfo::new(&pair));
-            }
-            return None;
+            let mut collision_info = if compute_collision_infos { sat::get_info_collisions(p1, p2, normal, distance) } else { CollisionInfo::default() };
+            collision_info.collision_pair = Some(pair.clone());
+            return Some(collision_info);
         }
     ).collect::<Vec<_>>();
 
@@ -83,21 +90,20 @@ pub(crate) fn narrow_phase_sat_st(broad_phase_data: &BroadPhaseData,
 
 pub(crate) fn narrow_phase_sat_mt(broad_phase_data: &BroadPhaseData,
                                   query: &NarrowPhaseQuery,
-                                  _compute_collision_infos: bool,
+                                  compute_collision_infos: bool,
 ) -> Vec<CollisionInfo>
 {
     let _span = info_span!("narrow_phase", name = "SAT").entered();
 
     let collision_infos = broad_phase_data.collision_pairs.par_iter().filter_map(
         |pair| {
-            let (p1, t1) = query.get(pair.entity_a).unwrap();
-            let (p2, t2) = query.get(pair.entity_b).unwrap();
-            let collided = sat::check_collision(&p1, &t1, &p2, &t2);
+            let Ok(p1) = query.get(pair.entity_a) else { return None; };
+            let Ok(p2) = query.get(pair.entity_b) else { return None; };
+            let Some((normal, distance)) = sat::find_min_overlap(p1, p2) else { return None; };
 
-            if collided == true {
-                return Some(CollisionInfo::new(&pair));
-            }
-            return None;
+            let mut collision_info = if compute_collision_infos { sat::get_info_collisions(p1, p2, normal, distance) } else { CollisionInfo::default() };
+            collision_info.collision_pair = Some(pair.clone());
+            return Some(collision_info);
         }
     ).collect::<Vec<_>>();
 
@@ -113,16 +119,13 @@ pub(crate) fn narrow_phase_gjk_mt(broad_phase_data: &BroadPhaseData,
     //fixme
     let collision_infos = broad_phase_data.collision_pairs.par_iter().filter_map(
         |pair| {
-            let (p1, t1) = query.get(pair.entity_a).unwrap();
-            let (p2, t2) = query.get(pair.entity_b).unwrap();
-            let (collided, simplex) = gjk::check_collision(&p1, &t1, &p2, &t2);
-
-            if collided == true {
-                let mut collision_info = if compute_collision_infos { gjk::get_info_collisions(&p1, &t1, &p2, &t2, simplex) } else { CollisionInfo::default() };
-                collision_info.collision_pair = Some(pair.clone());
-                return Some(collision_info);
-            }
-            return None;
+            let Ok(p1) = query.get(pair.entity_a) else { return None; };
+            let Ok(p2) = query.get(pair.entity_b) else { return None; };
+            let Some(contact) = gjk::find_contact(p1, p2) else { return None; };
+
+            let mut collision_info = if compute_collision_infos { gjk::get_contact_info(p1, p2, contact) } else { CollisionInfo::default() };
+            collision_info.collision_pair = Some(pair.clone());
+            return Some(collision_info);
         }
     ).collect::<Vec<_>>();
 
@@ -138,18 +141,82 @@ pub(crate) fn narrow_phase_gjk_st(broad_phase_data: &BroadPhaseData,
     //fixme
     let collision_infos = broad_phase_data.collision_pairs.iter().filter_map(
         |pair| {
-            let (p1, t1) = query.get(pair.entity_a).unwrap();
-            let (p2, t2) = query.get(pair.entity_b).unwrap();
-            let (collided, simplex) = gjk::check_collision(&p1, &t1, &p2, &t2);
-
-            if collided == true {
-                let mut collision_info = if compute_collision_infos { gjk::get_info_collisions(&p1, &t1, &p2, &t2, simplex) } else { CollisionInfo::default() };
-                collision_info.collision_pair = Some(pair.clone());
-                return Some(collision_info);
+            let Ok(p1) = query.get(pair.entity_a) else { return None; };
+            let Ok(p2) = query.get(pair.entity_b) else { return None; };
+            let Some(contact) = gjk::find_contact(p1, p2) else { return None; };
+
+            let mut collision_info = if compute_collision_infos { gjk::get_contact_info(p1, p2, contact) } else { CollisionInfo::default() };
+            collision_info.collision_pair = Some(pair.clone());
+            return Some(collision_info);
+        }
+    ).collect::<Vec<_>>();
+
+    return collision_infos;
+}
+
+pub(crate) fn narrow_phase_mpr_st(broad_phase_data: &BroadPhaseData,
+                                  query: &NarrowPhaseQuery,
+                                  compute_collision_infos: bool,
+) -> Vec<CollisionInfo>
+{
+    let _span = info_span!("narrow_phase", name = "MPR").entered();
+
+    let collision_infos = broad_phase_data.collision_pairs.iter().filter_map(
+        |pair| {
+            let Ok(p1) = query.get(pair.entity_a) else { return None; };
+            let Ok(p2) = query.get(pair.entity_b) else { return None; };
+            let Some((normal, distance)) = mpr::find_penetration(p1, p2) else { return None; };
+
+            let mut collision_info = if compute_collision_infos { mpr::get_info_collisions(p1, p2, normal, distance) } else { CollisionInfo::default() };
+            collision_info.collision_pair = Some(pair.clone());
+            return Some(collision_info);
+        }
+    ).collect::<Vec<_>>();
+
+    return collision_infos;
+}
+
+pub(crate) fn narrow_phase_mpr_mt(broad_phase_data: &BroadPhaseData,
+                                  query: &NarrowPhaseQuery,
+                                  compute_collision_infos: bool,
+) -> Vec<CollisionInfo>
+{
+    let _span = info_span!("narrow_phase", name = "MPR").entered();
+
+    let collision_infos = broad_phase_data.collision_pairs.par_iter().filter_map(
+        |pair| {
+            let Ok(p1) = query.get(pair.entity_a) else { return None; };
+            let Ok(p2) = query.get(pair.entity_b) else { return None; };
+            let Some((normal, distance)) = mpr::find_penetration(p1, p2) else { return None; };
+
+            let mut collision_info = if compute_collision_infos { mpr::get_info_collisions(p1, p2, normal, distance) } else { CollisionInfo::default() };
+            collision_info.collision_pair = Some(pair.clone());
+            return Some(collision_info);
+        }
+    ).collect::<Vec<_>>();
+
+    return collision_infos;
+}
+
+// Heightfields are split in cells, so they are not handled by the polygon algorithms above
+pub(crate) fn narrow_phase_heightfield(broad_phase_data: &BroadPhaseData,
+                                       query: &NarrowPhaseQuery,
+                                       heightfield_query: &NarrowPhaseHeightfieldQuery,
+                                       compute_collision_infos: bool,
+) -> Vec<CollisionInfo> {
+    let _span = info_span!("narrow_phase", name = "Heightfield").entered();
+
+    let collision_infos = broad_phase_data.collision_pairs.iter().flat_map(
+        |pair| {
+            if let (Ok((h, t_h)), Ok(p)) = (heightfield_query.get(pair.entity_a), query.get(pair.entity_b)) {
+                return heightfield::check_collision(pair, h, t_h, p, true, compute_collision_infos);
+            }
+            if let (Ok(p), Ok((h, t_h))) = (query.get(pair.entity_a), heightfield_query.get(pair.entity_b)) {
+                return heightfield::check_collision(pair, h, t_h, p, false, compute_collision_infos);
             }
-            return None;
+            return vec!();
         }
     ).collect::<Vec<_>>();
 
     return collision_infos;
-}
\ No newline at end of file
+}
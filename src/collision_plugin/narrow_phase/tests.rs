@@ -0,0 +1,505 @@
+// Unit tests of the narrow phase algorithms, on small hand built squares and triangles
+// whose expected normals and depths can be worked out by hand.
+
+use bevy::prelude::*;
+
+use crate::collision_plugin::heightfield_component::HeightfieldComponent;
+use crate::collision_plugin::narrow_phase::contacts::{ContactManifold, get_contact_manifold};
+use crate::collision_plugin::narrow_phase::gjk;
+use crate::collision_plugin::narrow_phase::mpr;
+use crate::collision_plugin::narrow_phase::raycast::{cast_ray_heightfield, cast_ray_polygon};
+use crate::collision_plugin::narrow_phase::sat;
+use crate::collision_plugin::narrow_phase::time_of_impact::{advance_until_contact, compute_time_of_impact, compute_time_of_impact_heightfield};
+use crate::collision_plugin::polygon_component::WorldPolygon;
+use crate::transform2d::Transform2d;
+
+const EPSILON: f32 = 0.001f32;
+
+// Axis aligned square, counter-clockwise
+fn square(half_size: f32, center: Vec2, radius: f32) -> WorldPolygon {
+    return WorldPolygon::from_points(vec!(
+        center + Vec2::new(-half_size, -half_size),
+        center + Vec2::new(half_size, -half_size),
+        center + Vec2::new(half_size, half_size),
+        center + Vec2::new(-half_size, half_size),
+    ), radius);
+}
+
+// Triangle pointing down, its tip at `tip`
+fn triangle(tip: Vec2, radius: f32) -> WorldPolygon {
+    return WorldPolygon::from_points(vec!(tip, tip + Vec2::new(1f32, 1.5f32), tip + Vec2::new(-1f32, 1.5f32)), radius);
+}
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!((actual - expected).abs() <= EPSILON, "{} != {}", actual, expected);
+}
+
+fn assert_close_vec(actual: Vec2, expected: Vec2) {
+    assert!(actual.distance(expected) <= EPSILON, "{:?} != {:?}", actual, expected);
+}
+
+// Conservative advancement stops up to the touch distance before the contact
+fn assert_toi(toi: f32, gap: f32, speed: f32) {
+    assert!(toi <= gap / speed + EPSILON && toi >= (gap - 0.01f32) / speed - EPSILON, "{} for a gap of {} at {}", toi, gap, speed);
+}
+
+fn assert_manifold(manifold: &ContactManifold, points: &[Vec2], depths: &[f32]) {
+    assert_eq!(manifold.points.len(), points.len(), "{:?}", manifold.points);
+    assert_eq!(manifold.depths.len(), depths.len(), "{:?}", manifold.depths);
+    assert_eq!(manifold.feature_ids.len(), points.len());
+    for (&actual, &expected) in manifold.points.iter().zip(points) {
+        assert_close_vec(actual, expected);
+    }
+    for (&actual, &expected) in manifold.depths.iter().zip(depths) {
+        assert_close(actual, expected);
+    }
+}
+
+// SAT
+
+#[test]
+fn sat_finds_the_axis_of_least_overlap() {
+    let (normal, depth) = sat::find_min_overlap(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(1.5f32, 0.2f32), 0f32)).unwrap();
+    assert_close_vec(normal, Vec2::NEG_X);
+    assert_close(depth, 0.5f32);
+
+    // The normal always pushes the first polygon out
+    let (normal, depth) = sat::find_min_overlap(&square(1f32, Vec2::new(1.5f32, 0.2f32), 0f32), &square(1f32, Vec2::ZERO, 0f32)).unwrap();
+    assert_close_vec(normal, Vec2::X);
+    assert_close(depth, 0.5f32);
+}
+
+#[test]
+fn sat_separated_or_touching_polygons_dont_collide() {
+    assert!(sat::find_min_overlap(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(2.5f32, 0f32), 0f32)).is_none());
+    assert!(sat::find_min_overlap(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(2f32, 0f32), 0f32)).is_none());
+
+    // The boolean test agrees
+    assert!(sat::check_collision(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(1.5f32, 0.2f32), 0f32)));
+    assert!(!sat::check_collision(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(2f32, 0f32), 0f32)));
+    assert!(!sat::check_collision(&square(1f32, Vec2::ZERO, 0.5f32), &square(1f32, Vec2::splat(2.8f32), 0.5f32)));
+}
+
+#[test]
+fn sat_contained_polygon_takes_the_shortest_way_out() {
+    let (normal, depth) = sat::find_min_overlap(&square(0.5f32, Vec2::new(0.6f32, 0f32), 0f32), &square(2f32, Vec2::ZERO, 0f32)).unwrap();
+    assert_close_vec(normal, Vec2::X);
+    assert_close(depth, 1.9f32);
+}
+
+#[test]
+fn sat_triangle_tip_is_pushed_out_of_the_face() {
+    let (normal, depth) = sat::find_min_overlap(&triangle(Vec2::new(0f32, 0.5f32), 0f32), &square(1f32, Vec2::ZERO, 0f32)).unwrap();
+    assert_close_vec(normal, Vec2::Y);
+    assert_close(depth, 0.5f32);
+}
+
+#[test]
+fn sat_rounded_corners_use_the_vertex_axis() {
+    // Core corners (1, 1) and (1.5, 1.5) are closer than the radii, but only along the diagonal
+    let (normal, depth) = sat::find_min_overlap(&square(1f32, Vec2::ZERO, 0.5f32), &square(1f32, Vec2::splat(2.5f32), 0.5f32)).unwrap();
+    assert_close_vec(normal, -Vec2::ONE.normalize());
+    assert_close(depth, 1f32 - 0.5f32.sqrt());
+
+    // The edge normals overlap, the vertex axis separates the rounded corners
+    assert!(sat::find_min_overlap(&square(1f32, Vec2::ZERO, 0.5f32), &square(1f32, Vec2::splat(2.8f32), 0.5f32)).is_none());
+}
+
+#[test]
+fn sat_handles_degenerate_polygons() {
+    // A segment has two opposite normals
+    let segment = WorldPolygon::from_points(vec!(Vec2::new(-0.5f32, 0.8f32), Vec2::new(0.5f32, 0.8f32)), 0f32);
+    let (normal, depth) = sat::find_min_overlap(&segment, &square(1f32, Vec2::ZERO, 0f32)).unwrap();
+    assert_close_vec(normal, Vec2::Y);
+    assert_close(depth, 0.2f32);
+
+    // The zero normal of a repeated vertex is skipped
+    let mut repeated = square(1f32, Vec2::ZERO, 0f32);
+    repeated.points.insert(1, repeated.points[0]);
+    let repeated = WorldPolygon::from_points(repeated.points, 0f32);
+    let (normal, depth) = sat::find_min_overlap(&repeated, &square(1f32, Vec2::new(1.5f32, 0.2f32), 0f32)).unwrap();
+    assert_close_vec(normal, Vec2::NEG_X);
+    assert_close(depth, 0.5f32);
+}
+
+// Contact manifold
+
+#[test]
+fn manifold_clips_the_incident_edge_by_the_reference_edge() {
+    let manifold = get_contact_manifold(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(1.5f32, 0.5f32), 0f32), Vec2::X);
+
+    // The left edge of the second square, clipped at the top of the first one
+    assert_manifold(&manifold, &[Vec2::new(0.5f32, -0.5f32), Vec2::new(0.5f32, 1f32)], &[0.5f32, 0.5f32]);
+    // Reference edge 1, incident edge 3, the end of the incident edge then the clipped point, not flipped
+    assert_eq!(manifold.feature_ids, vec!(0x01030100, 0x01030300));
+}
+
+#[test]
+fn manifold_feature_ids_are_stable_while_sliding() {
+    let before = get_contact_manifold(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(1.5f32, 0.5f32), 0f32), Vec2::X);
+    let after = get_contact_manifold(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(1.45f32, 0.6f32), 0f32), Vec2::X);
+
+    assert_manifold(&after, &[Vec2::new(0.45f32, -0.4f32), Vec2::new(0.45f32, 1f32)], &[0.55f32, 0.55f32]);
+    assert_eq!(before.feature_ids, after.feature_ids);
+}
+
+#[test]
+fn manifold_uses_the_most_perpendicular_edge_as_reference() {
+    // The top of the square is the reference edge, only the tip of the triangle is behind it
+    let manifold = get_contact_manifold(&triangle(Vec2::new(0f32, 0.5f32), 0f32), &square(1f32, Vec2::ZERO, 0f32), Vec2::NEG_Y);
+
+    assert_manifold(&manifold, &[Vec2::new(0f32, 0.5f32)], &[0.5f32]);
+    assert_eq!(manifold.feature_ids, vec!(0x02000001));
+}
+
+#[test]
+fn manifold_points_are_on_the_rounded_surface() {
+    // Cores 0.3 apart with a skin of 0.25 each, the points are moved onto the skin of the incident polygon
+    let manifold = get_contact_manifold(&square(1f32, Vec2::ZERO, 0.25f32), &square(1f32, Vec2::new(2.3f32, 0f32), 0.25f32), Vec2::X);
+
+    assert_manifold(&manifold, &[Vec2::new(1.05f32, 1f32), Vec2::new(1.05f32, -1f32)], &[0.2f32, 0.2f32]);
+    assert_eq!(manifold.feature_ids, vec!(0x01030000, 0x01030100));
+}
+
+#[test]
+fn manifold_handles_segments() {
+    let segment = WorldPolygon::from_points(vec!(Vec2::new(0.8f32, 0.5f32), Vec2::new(0.8f32, -0.5f32)), 0f32);
+    let manifold = get_contact_manifold(&square(1f32, Vec2::ZERO, 0f32), &segment, Vec2::X);
+    assert_manifold(&manifold, &[Vec2::new(0.8f32, -0.5f32), Vec2::new(0.8f32, 0.5f32)], &[0.2f32, 0.2f32]);
+
+    // A segment as reference edge faces the wrong way, the deepest point of p1 is used instead
+    let segment = WorldPolygon::from_points(vec!(Vec2::new(-0.5f32, 0.8f32), Vec2::new(0.5f32, 0.8f32)), 0f32);
+    let manifold = get_contact_manifold(&segment, &square(1f32, Vec2::ZERO, 0f32), Vec2::NEG_Y);
+    assert_manifold(&manifold, &[Vec2::new(0.5f32, 0.8f32)], &[0.2f32]);
+}
+
+// GJK distance
+
+#[test]
+fn distance_between_faces() {
+    let distance = gjk::get_distance(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(3f32, 0.5f32), 0f32)).unwrap();
+    assert_close(distance.distance, 1f32);
+    assert_close_vec(distance.normal, Vec2::X);
+    // Any pair of points facing each other is a closest pair
+    assert_close(distance.point_a.x, 1f32);
+    assert!((-0.5f32..=1f32).contains(&distance.point_a.y));
+    assert_close_vec(distance.point_b, distance.point_a + Vec2::X);
+
+    // Swapping the polygons flips the normal
+    let distance = gjk::get_distance(&square(1f32, Vec2::new(3f32, 0.5f32), 0f32), &square(1f32, Vec2::ZERO, 0f32)).unwrap();
+    assert_close(distance.distance, 1f32);
+    assert_close_vec(distance.normal, Vec2::NEG_X);
+}
+
+#[test]
+fn distance_between_corners() {
+    let distance = gjk::get_distance(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::splat(3f32), 0f32)).unwrap();
+    assert_close(distance.distance, 2f32.sqrt());
+    assert_close_vec(distance.normal, Vec2::ONE.normalize());
+    assert_close_vec(distance.point_a, Vec2::ONE);
+    assert_close_vec(distance.point_b, Vec2::splat(2f32));
+}
+
+#[test]
+fn distance_from_a_triangle_tip() {
+    let distance = gjk::get_distance(&triangle(Vec2::new(0.2f32, 2f32), 0f32), &square(1f32, Vec2::ZERO, 0f32)).unwrap();
+    assert_close(distance.distance, 1f32);
+    assert_close_vec(distance.normal, Vec2::NEG_Y);
+    assert_close_vec(distance.point_a, Vec2::new(0.2f32, 2f32));
+    assert_close_vec(distance.point_b, Vec2::new(0.2f32, 1f32));
+}
+
+#[test]
+fn distance_removes_the_rounded_skins() {
+    let p1 = square(1f32, Vec2::ZERO, 0.25f32);
+    let p2 = square(1f32, Vec2::new(3f32, 0.5f32), 0.25f32);
+
+    assert_close(gjk::get_core_distance(&p1, &p2).unwrap().distance, 1f32);
+    let distance = gjk::get_distance(&p1, &p2).unwrap();
+    assert_close(distance.distance, 0.5f32);
+    assert_close(distance.point_a.x, 1.25f32);
+    assert_close(distance.point_b.x, 1.75f32);
+
+    // Only the skins overlap
+    let p2 = square(1f32, Vec2::new(2.3f32, 0f32), 0.25f32);
+    assert_close(gjk::get_core_distance(&p1, &p2).unwrap().distance, 0.3f32);
+    assert!(gjk::get_distance(&p1, &p2).is_none());
+}
+
+#[test]
+fn distance_is_none_when_overlapping_or_touching() {
+    assert!(gjk::get_distance(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(1.5f32, 0f32), 0f32)).is_none());
+    assert!(gjk::get_distance(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(2f32, 0f32), 0f32)).is_none());
+    assert!(gjk::get_distance(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::ZERO, 0f32)).is_none());
+}
+
+#[test]
+fn distance_to_points_and_segments() {
+    let point = WorldPolygon::from_points(vec!(Vec2::new(3f32, 0f32)), 0f32);
+    let distance = gjk::get_distance(&point, &square(1f32, Vec2::ZERO, 0f32)).unwrap();
+    assert_close(distance.distance, 2f32);
+    assert_close_vec(distance.normal, Vec2::NEG_X);
+    assert_close_vec(distance.point_a, Vec2::new(3f32, 0f32));
+    assert_close_vec(distance.point_b, Vec2::new(1f32, 0f32));
+
+    // A rounded point is a circle
+    let circle = WorldPolygon::from_points(vec!(Vec2::new(3f32, 0f32)), 0.5f32);
+    assert_close(gjk::get_distance(&circle, &square(1f32, Vec2::ZERO, 0f32)).unwrap().distance, 1.5f32);
+
+    let segment = WorldPolygon::from_points(vec!(Vec2::new(2f32, -3f32), Vec2::new(2f32, 3f32)), 0f32);
+    let distance = gjk::get_distance(&square(1f32, Vec2::ZERO, 0f32), &segment).unwrap();
+    assert_close(distance.distance, 1f32);
+    assert_close_vec(distance.normal, Vec2::X);
+}
+
+// Raycasts
+
+#[test]
+fn ray_hits_the_facing_edge() {
+    let hit = cast_ray_polygon(&square(1f32, Vec2::ZERO, 0f32), Vec2::new(-5f32, 0.5f32), Vec2::X, 10f32).unwrap();
+    assert_close(hit.toi, 4f32);
+    assert_close_vec(hit.normal, Vec2::NEG_X);
+
+    // The toi is in units of the direction
+    let hit = cast_ray_polygon(&square(1f32, Vec2::ZERO, 0f32), Vec2::new(-5f32, 0.5f32), Vec2::X * 2f32, 10f32).unwrap();
+    assert_close(hit.toi, 2f32);
+
+    let hit = cast_ray_polygon(&triangle(Vec2::new(0f32, 0.5f32), 0f32), Vec2::new(0.5f32, -3f32), Vec2::Y, 10f32).unwrap();
+    assert_close(hit.toi, 4.25f32);
+    assert_close_vec(hit.normal, Vec2::new(1.5f32, -1f32).normalize());
+}
+
+#[test]
+fn ray_misses() {
+    let polygon = square(1f32, Vec2::ZERO, 0f32);
+    assert!(cast_ray_polygon(&polygon, Vec2::new(-5f32, 2f32), Vec2::X, 10f32).is_none());
+    assert!(cast_ray_polygon(&polygon, Vec2::new(-5f32, 0f32), Vec2::NEG_X, 10f32).is_none());
+    assert!(cast_ray_polygon(&polygon, Vec2::new(-5f32, 0f32), Vec2::X, 3f32).is_none());
+}
+
+#[test]
+fn ray_starting_inside_hits_immediately() {
+    for radius in [0f32, 0.5f32] {
+        let hit = cast_ray_polygon(&square(1f32, Vec2::ZERO, radius), Vec2::new(0.5f32, 0f32), Vec2::Y, 10f32).unwrap();
+        assert_close(hit.toi, 0f32);
+        assert_close_vec(hit.normal, Vec2::NEG_Y);
+    }
+
+    // Inside the skin only
+    let hit = cast_ray_polygon(&square(1f32, Vec2::ZERO, 0.5f32), Vec2::new(-1.2f32, 0f32), Vec2::X, 10f32).unwrap();
+    assert_close(hit.toi, 0f32);
+}
+
+#[test]
+fn ray_hits_the_rounded_skin() {
+    let polygon = square(1f32, Vec2::ZERO, 0.5f32);
+
+    // Edge pushed out by the radius
+    let hit = cast_ray_polygon(&polygon, Vec2::new(-5f32, 0f32), Vec2::X, 10f32).unwrap();
+    assert_close(hit.toi, 3.5f32);
+    assert_close_vec(hit.normal, Vec2::NEG_X);
+
+    // Circle on the corner (-1, 1), entered at (-1.4, 1.3)
+    let hit = cast_ray_polygon(&polygon, Vec2::new(-5f32, 1.3f32), Vec2::X, 10f32).unwrap();
+    assert_close(hit.toi, 3.6f32);
+    assert_close_vec(hit.normal, Vec2::new(-0.8f32, 0.6f32));
+
+    assert!(cast_ray_polygon(&polygon, Vec2::new(-5f32, 1.6f32), Vec2::X, 10f32).is_none());
+    assert!(cast_ray_polygon(&polygon, Vec2::new(-5f32, 0f32), Vec2::X, 3f32).is_none());
+}
+
+#[test]
+fn ray_hits_the_heightfield_surface() {
+    let heightfield = HeightfieldComponent::new(vec!(0f32, 1f32, 1f32, 0f32), 1f32);
+    let transform = Transform2d {
+        translation: Vec2::ZERO,
+        rotation: 0f32,
+        scale: 1f32,
+    };
+
+    let hit = cast_ray_heightfield(&heightfield, &transform, Vec2::new(1.5f32, 5f32), Vec2::NEG_Y, 10f32).unwrap();
+    assert_close(hit.toi, 4f32);
+    assert_close_vec(hit.normal, Vec2::Y);
+
+    // On the slope of the first cell
+    let hit = cast_ray_heightfield(&heightfield, &transform, Vec2::new(-5f32, 0.5f32), Vec2::X, 10f32).unwrap();
+    assert_close(hit.toi, 5.5f32);
+    assert_close_vec(hit.normal, Vec2::new(-1f32, 1f32).normalize());
+
+    // Beyond the last sample
+    assert!(cast_ray_heightfield(&heightfield, &transform, Vec2::new(3.5f32, 5f32), Vec2::NEG_Y, 10f32).is_none());
+}
+
+#[test]
+fn ray_hits_a_transformed_heightfield() {
+    let heightfield = HeightfieldComponent::new(vec!(0f32, 1f32, 1f32, 0f32), 1f32);
+    let transform = Transform2d {
+        translation: Vec2::new(10f32, 0f32),
+        rotation: 0f32,
+        scale: 2f32,
+    };
+
+    let hit = cast_ray_heightfield(&heightfield, &transform, Vec2::new(13f32, 10f32), Vec2::NEG_Y, 20f32).unwrap();
+    assert_close(hit.toi, 8f32);
+    assert_close_vec(hit.normal, Vec2::Y);
+
+    assert!(cast_ray_heightfield(&heightfield, &transform, Vec2::new(3f32, 10f32), Vec2::NEG_Y, 20f32).is_none());
+}
+
+// Time of impact
+
+#[test]
+fn time_of_impact_of_a_moving_square() {
+    let p1 = square(1f32, Vec2::ZERO, 0f32);
+    let p2 = square(1f32, Vec2::new(5f32, 0f32), 0f32);
+
+    let time_of_impact = compute_time_of_impact(&p1, Vec2::new(10f32, 0f32), &p2, Vec2::ZERO, 1f32).unwrap();
+    assert_toi(time_of_impact.toi, 3f32, 10f32);
+    assert_close_vec(time_of_impact.normal, Vec2::NEG_X);
+    assert_close(time_of_impact.point.x, 4f32);
+    assert!(!time_of_impact.overlapping);
+
+    // Only the relative velocity matters, the point follows the second polygon
+    let time_of_impact = compute_time_of_impact(&p1, Vec2::new(5f32, 0f32), &p2, Vec2::new(-5f32, 0f32), 1f32).unwrap();
+    assert_toi(time_of_impact.toi, 3f32, 10f32);
+    assert_close(time_of_impact.point.x, 4f32 - 5f32 * time_of_impact.toi);
+}
+
+#[test]
+fn time_of_impact_misses() {
+    let p1 = square(1f32, Vec2::ZERO, 0f32);
+    let p2 = square(1f32, Vec2::new(5f32, 0f32), 0f32);
+
+    assert!(compute_time_of_impact(&p1, Vec2::new(0f32, 10f32), &p2, Vec2::ZERO, 1f32).is_none());
+    assert!(compute_time_of_impact(&p1, Vec2::new(-10f32, 0f32), &p2, Vec2::ZERO, 1f32).is_none());
+    assert!(compute_time_of_impact(&p1, Vec2::new(10f32, 0f32), &p2, Vec2::ZERO, 0.2f32).is_none());
+}
+
+#[test]
+fn time_of_impact_of_overlapping_or_touching_shapes() {
+    let p1 = square(1f32, Vec2::ZERO, 0f32);
+
+    let time_of_impact = compute_time_of_impact(&p1, Vec2::new(10f32, 0f32), &square(1f32, Vec2::new(1.5f32, 0.2f32), 0f32), Vec2::ZERO, 1f32).unwrap();
+    assert_close(time_of_impact.toi, 0f32);
+    assert_close_vec(time_of_impact.normal, Vec2::NEG_X);
+    assert!(time_of_impact.overlapping);
+
+    // Resting against each other, only a hit when moving closer
+    let p2 = square(1f32, Vec2::new(2.005f32, 0f32), 0f32);
+    assert!(compute_time_of_impact(&p1, Vec2::new(-10f32, 0f32), &p2, Vec2::ZERO, 1f32).is_none());
+    assert!(compute_time_of_impact(&p1, Vec2::new(0f32, 10f32), &p2, Vec2::ZERO, 1f32).is_none());
+    let time_of_impact = compute_time_of_impact(&p1, Vec2::new(10f32, 0f32), &p2, Vec2::ZERO, 1f32).unwrap();
+    assert_close(time_of_impact.toi, 0f32);
+    assert!(!time_of_impact.overlapping);
+}
+
+#[test]
+fn time_of_impact_needs_to_reach_the_contact() {
+    // A circle grazing the top left corner of the square, its path passing within the touch distance
+    let circle = WorldPolygon::from_points(vec!(Vec2::new(-10f32, 2.0099f32)), 1f32);
+    let p2 = square(1f32, Vec2::ZERO, 0f32);
+    let velocity = Vec2::new(20f32, 0f32);
+
+    let time_of_impact = compute_time_of_impact(&circle, velocity, &p2, Vec2::ZERO, 1f32).unwrap();
+    let mut moved = circle.clone();
+    moved.translate(velocity * time_of_impact.toi);
+    assert!(gjk::get_distance(&moved, &p2).map_or(true, |distance| distance.distance <= 0.01f32));
+
+    // Stopping before the shapes touch isn't a hit, even if they were getting closer
+    assert!(advance_until_contact(&circle, velocity, &p2, Vec2::ZERO, 1f32, 1).is_none());
+}
+
+#[test]
+fn time_of_impact_of_rounded_and_triangle_shapes() {
+    let time_of_impact = compute_time_of_impact(&square(1f32, Vec2::ZERO, 0.5f32), Vec2::new(10f32, 0f32),
+                                                &square(1f32, Vec2::new(5f32, 0f32), 0.5f32), Vec2::ZERO, 1f32).unwrap();
+    assert_toi(time_of_impact.toi, 2f32, 10f32);
+    assert_close_vec(time_of_impact.normal, Vec2::NEG_X);
+
+    let time_of_impact = compute_time_of_impact(&triangle(Vec2::new(0f32, 3f32), 0f32), Vec2::new(0f32, -10f32),
+                                                &square(1f32, Vec2::ZERO, 0f32), Vec2::ZERO, 1f32).unwrap();
+    assert_toi(time_of_impact.toi, 2f32, 10f32);
+    assert_close_vec(time_of_impact.normal, Vec2::Y);
+    assert_close_vec(time_of_impact.point, Vec2::new(0f32, 1f32));
+}
+
+#[test]
+fn time_of_impact_with_a_heightfield() {
+    let heightfield = HeightfieldComponent::new(vec!(0f32, 1f32, 1f32, 0f32), 1f32);
+    let transform = Transform2d {
+        translation: Vec2::ZERO,
+        rotation: 0f32,
+        scale: 1f32,
+    };
+
+    let time_of_impact = compute_time_of_impact_heightfield(&square(0.5f32, Vec2::new(1.5f32, 3f32), 0f32), Vec2::new(0f32, -10f32),
+                                                            &heightfield, &transform, 1f32).unwrap();
+    assert_toi(time_of_impact.toi, 1.5f32, 10f32);
+    assert_close_vec(time_of_impact.normal, Vec2::Y);
+
+    assert!(compute_time_of_impact_heightfield(&square(0.5f32, Vec2::new(5f32, 3f32), 0f32), Vec2::new(0f32, -10f32),
+                                               &heightfield, &transform, 1f32).is_none());
+}
+
+// MPR
+
+#[test]
+fn mpr_depth_along_the_centers() {
+    let (normal, depth) = mpr::find_penetration(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(1.5f32, 0.5f32), 0f32)).unwrap();
+    assert_close_vec(normal, Vec2::NEG_X);
+    assert_close(depth, 0.5f32);
+
+    let (normal, depth) = mpr::find_penetration(&triangle(Vec2::new(0f32, 0.5f32), 0f32), &square(1f32, Vec2::ZERO, 0f32)).unwrap();
+    assert_close_vec(normal, Vec2::Y);
+    assert_close(depth, 0.5f32);
+}
+
+#[test]
+fn mpr_separated_polygons_dont_collide() {
+    assert!(mpr::find_penetration(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(2.5f32, 0f32), 0f32)).is_none());
+    assert!(mpr::find_penetration(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::splat(2.5f32), 0f32)).is_none());
+    assert!(mpr::find_penetration(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::new(2f32, 0f32), 0f32)).is_none());
+}
+
+#[test]
+fn mpr_depth_is_never_below_the_minimum_translation() {
+    let p1 = WorldPolygon::from_points(vec!(Vec2::new(-3f32, -0.5f32), Vec2::new(3f32, -0.5f32), Vec2::new(3f32, 0.5f32), Vec2::new(-3f32, 0.5f32)), 0f32);
+    let p2 = square(1f32, Vec2::new(2.5f32, 0.8f32), 0f32);
+
+    let (normal, depth) = mpr::find_penetration(&p1, &p2).unwrap();
+    let (_, min_depth) = sat::find_min_overlap(&p1, &p2).unwrap();
+    assert!(normal.dot(Vec2::new(2.5f32, 0.8f32)) < 0f32);
+    assert!(depth >= min_depth - EPSILON, "{} < {}", depth, min_depth);
+}
+
+#[test]
+fn mpr_adds_the_rounded_skins() {
+    // Overlapping cores
+    let (normal, depth) = mpr::find_penetration(&square(1f32, Vec2::ZERO, 0.25f32), &square(1f32, Vec2::new(1.5f32, 0f32), 0.25f32)).unwrap();
+    assert_close_vec(normal, Vec2::NEG_X);
+    assert_close(depth, 1f32);
+
+    // Only the skins overlap
+    let (normal, depth) = mpr::find_penetration(&square(1f32, Vec2::ZERO, 0.25f32), &square(1f32, Vec2::new(2.3f32, 0f32), 0.25f32)).unwrap();
+    assert_close_vec(normal, Vec2::NEG_X);
+    assert_close(depth, 0.2f32);
+
+    let (normal, depth) = mpr::find_penetration(&square(1f32, Vec2::ZERO, 0.5f32), &square(1f32, Vec2::splat(2.5f32), 0.5f32)).unwrap();
+    assert_close_vec(normal, -Vec2::ONE.normalize());
+    assert_close(depth, 1f32 - 0.5f32.sqrt());
+    assert!(mpr::find_penetration(&square(1f32, Vec2::ZERO, 0.5f32), &square(1f32, Vec2::splat(2.8f32), 0.5f32)).is_none());
+}
+
+#[test]
+fn mpr_handles_degenerate_polygons() {
+    // Matching centers, the nudged interior point still finds a way out
+    let (normal, depth) = mpr::find_penetration(&square(1f32, Vec2::ZERO, 0f32), &square(1f32, Vec2::ZERO, 0f32)).unwrap();
+    assert_close(normal.length(), 1f32);
+    assert_close(depth, 2f32);
+
+    // A circle is a rounded point
+    let circle = WorldPolygon::from_points(vec!(Vec2::new(0.5f32, 0f32)), 0.7f32);
+    let (normal, depth) = mpr::find_penetration(&circle, &square(1f32, Vec2::ZERO, 0f32)).unwrap();
+    assert_close_vec(normal, Vec2::X);
+    assert_close(depth, 1.2f32);
+}
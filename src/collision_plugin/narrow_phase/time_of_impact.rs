@@ -0,0 +1,144 @@
+use bevy::prelude::*;
+
+use crate::collision_plugin::heightfield_component::HeightfieldComponent;
+use crate::collision_plugin::narrow_phase::gjk;
+use crate::collision_plugin::polygon_component::WorldPolygon;
+use crate::transform2d::Transform2d;
+
+// Shapes closer than this are considered touching
+const TOUCH_DISTANCE: f32 = 0.01f32;
+const MAX_ITERATIONS: usize = 32;
+
+/// First contact of two translating shapes. The point is on the surface of the second shape,
+/// and the normal points out of it, toward the first one.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TimeOfImpact {
+    pub toi: f32,
+    pub point: Vec2,
+    pub normal: Vec2,
+    /// The shapes were already overlapping at the start
+    pub overlapping: bool,
+}
+
+// Contact of shapes which already overlap, the normal pushes p1 out of p2
+fn get_initial_contact(p1: &WorldPolygon, p2: &WorldPolygon, relative_velocity: Vec2) -> TimeOfImpact {
+    let leading_point = p1.find_furthest_point(relative_velocity);
+
+    if let Some(contact) = gjk::find_contact(p1, p2) {
+        let collision_info = gjk::get_contact_info(p1, p2, contact);
+        return TimeOfImpact {
+            toi: 0f32,
+            point: collision_info.location.first().cloned().unwrap_or(leading_point),
+            normal: collision_info.normal,
+            overlapping: true,
+        };
+    }
+
+    // Touching without overlapping
+    return TimeOfImpact {
+        toi: 0f32,
+        point: leading_point,
+        normal: -relative_velocity.normalize_or_zero(),
+        overlapping: true,
+    };
+}
+
+/// Conservative advancement: the shapes are moved along their velocity by the distance between them
+/// divided by their closing speed, which can't make them overlap, until they touch.
+/// Rotations are ignored, None if they don't touch before `max_toi`, or still haven't touched once out of iterations.
+pub(crate) fn compute_time_of_impact(p1: &WorldPolygon, v1: Vec2,
+                                     p2: &WorldPolygon, v2: Vec2,
+                                     max_toi: f32,
+) -> Option<TimeOfImpact> {
+    return advance_until_contact(p1, v1, p2, v2, max_toi, MAX_ITERATIONS);
+}
+
+// Conservative advancement loop, with the number of iterations as a parameter
+pub(super) fn advance_until_contact(p1: &WorldPolygon, v1: Vec2,
+                                    p2: &WorldPolygon, v2: Vec2,
+                                    max_toi: f32,
+                                    max_iterations: usize,
+) -> Option<TimeOfImpact> {
+    let relative_velocity = v1 - v2;
+    let mut toi = 0f32;
+    let mut last_contact = None;
+
+    // Only the relative motion matters, p1 is moved while p2 stays at its start position
+    let mut p1_toi = p1.clone();
+    let mut moved_toi = 0f32;
+    let mut converged = false;
+
+    for _ in 0..max_iterations {
+        p1_toi.translate(relative_velocity * (toi - moved_toi));
+        moved_toi = toi;
+
+        let Some(distance_info) = gjk::get_distance(&p1_toi, p2) else {
+            if toi == 0f32 {
+                return Some(get_initial_contact(p1, p2, relative_velocity));
+            }
+            // Overlapping because of rounding errors, the last separated pose is kept
+            converged = true;
+            break;
+        };
+        last_contact = Some((toi, distance_info));
+
+        let closing_speed = relative_velocity.dot(distance_info.normal);
+        if distance_info.distance <= TOUCH_DISTANCE {
+            // Shapes resting against each other only hit if they move closer
+            if toi == 0f32 && closing_speed <= 0f32 {
+                return None;
+            }
+            converged = true;
+            break;
+        }
+
+        if closing_speed <= 0f32 {
+            return None;
+        }
+
+        // Stop a bit before touching, so the shapes never overlap because of rounding errors
+        toi += (distance_info.distance - TOUCH_DISTANCE * 0.5f32) / closing_speed;
+        if toi > max_toi {
+            return None;
+        }
+    }
+
+    // Out of iterations while still apart, the last pose isn't a contact
+    if !converged {
+        return None;
+    }
+
+    // Closest points of the last step that didn't overlap
+    let (toi, distance_info) = last_contact?;
+    return Some(TimeOfImpact {
+        toi,
+        point: distance_info.point_b + v2 * toi,
+        normal: -distance_info.normal,
+        overlapping: false,
+    });
+}
+
+/// Only the cells under the swept bounds of the polygon are tested
+pub(crate) fn compute_time_of_impact_heightfield(polygon: &WorldPolygon, velocity: Vec2,
+                                                 heightfield: &HeightfieldComponent, t_heightfield: &Transform2d,
+                                                 max_toi: f32,
+) -> Option<TimeOfImpact> {
+    let (min, max) = polygon.get_bounds();
+    let displacement = velocity * max_toi;
+    let min = min + displacement.min(Vec2::ZERO);
+    let max = max + displacement.max(Vec2::ZERO);
+
+    let mut closest: Option<TimeOfImpact> = None;
+    for cell in heightfield.get_cell_range_in_aabb(t_heightfield, min, max) {
+        let cell_polygon = heightfield.get_cell_world_polygon(cell, t_heightfield);
+        let max_toi = closest.map_or(max_toi, |closest| closest.toi);
+
+        if let Some(time_of_impact) = compute_time_of_impact(polygon, velocity, &cell_polygon, Vec2::ZERO, max_toi) {
+            if closest.map_or(true, |closest| time_of_impact.toi < closest.toi) {
+                closest = Some(time_of_impact);
+            }
+        }
+    }
+
+    return closest;
+}
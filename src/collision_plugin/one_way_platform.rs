@@ -0,0 +1,119 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::collision_plugin::data_structs::{CollisionInfo, CollisionResponseData, NarrowPhaseData};
+use crate::collision_plugin::events::pair_key;
+use crate::collision_plugin::rigidbody::RigidBody2d;
+use crate::collision_plugin::TIMESTEP;
+use crate::transform2d::Transform2d;
+
+// Contacts whose normal deviates more than this from the platform's normal are ignored, such as the platform's sides
+const MIN_NORMAL_ALIGNMENT: f32 = 0.5f32;
+// Penetration accepted on top of the distance travelled toward the platform during a step
+const PENETRATION_SLOP: f32 = 0.5f32;
+
+/// Collider only blocking the bodies coming from the side its normal points to, such as a platform
+/// that can be jumped through from below. The normal is in the local space of the collider.
+/// Bodies going through still send the collision events, only the collision response ignores them.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct OneWayPlatform {
+    pub normal: Vec2,
+}
+
+impl Default for OneWayPlatform {
+    fn default() -> Self {
+        OneWayPlatform {
+            normal: Vec2::Y,
+        }
+    }
+}
+
+impl OneWayPlatform {
+    pub fn new(normal: Vec2) -> Self {
+        return Self {
+            normal: normal.normalize_or_zero(),
+        };
+    }
+
+    pub fn get_world_normal(&self, transform: &Transform2d) -> Vec2 {
+        return transform.rotate(self.normal);
+    }
+
+    /// Whether a body pushed out of the platform along `push_direction` is on the blocking side
+    pub fn is_blocking(&self, transform: &Transform2d, push_direction: Vec2) -> bool {
+        return push_direction.dot(self.get_world_normal(transform)) >= MIN_NORMAL_ALIGNMENT;
+    }
+}
+
+// Direction in which the collision response pushes `body`
+fn get_push_direction(collision_info: &CollisionInfo, body: Entity) -> Vec2 {
+    let pair = collision_info.collision_pair.unwrap();
+    if pair.entity_a == body {
+        return collision_info.normal;
+    }
+    return -collision_info.normal;
+}
+
+// A new contact is kept if it pushes the body out of the platform's side,
+// and the body didn't go deeper than the distance it travelled toward the platform during the step
+fn is_blocked_by_platform(
+    collision_info: &CollisionInfo,
+    platform_entity: Entity,
+    body_entity: Entity,
+    q_platforms: &Query<(&OneWayPlatform, &Transform2d)>,
+    q_rigidbodies: &Query<&RigidBody2d>,
+) -> bool {
+    let Ok((platform, transform)) = q_platforms.get(platform_entity) else { return true; };
+    if !platform.is_blocking(transform, get_push_direction(collision_info, body_entity)) {
+        return false;
+    }
+
+    let normal = platform.get_world_normal(transform);
+    let get_speed = |entity| q_rigidbodies.get(entity).map_or(Vec2::ZERO, |rigidbody| rigidbody.linear_speed);
+    let approach_speed = (get_speed(platform_entity) - get_speed(body_entity)).dot(normal).max(0f32);
+
+    return collision_info.distance <= approach_speed * TIMESTEP as f32 + PENETRATION_SLOP;
+}
+
+//Find the pairs of one way platforms with the bodies coming from the wrong side, which the collision response skips.
+//Pairs which were touching on the previous step keep their decision until they separate,
+//so a body resting on a platform doesn't fall through it and a body going through doesn't get pushed out mid way
+pub(crate) fn one_way_platform_system(
+    narrow_phase_data: Res<NarrowPhaseData>,
+    mut collision_response_data: ResMut<CollisionResponseData>,
+    q_platforms: Query<(&OneWayPlatform, &Transform2d)>,
+    q_rigidbodies: Query<&RigidBody2d>,
+)
+{
+    let _span = info_span!("one_way_platform", name = "System").entered();
+
+    let previous_passing = std::mem::take(&mut collision_response_data.passing_pairs);
+    let mut decisions: HashMap<(Entity, Entity), bool> = HashMap::new();
+
+    for collision_info in &narrow_phase_data.collision_infos {
+        let Some(pair) = collision_info.collision_pair else { continue; };
+        let key = pair_key(pair.entity_a, pair.entity_b);
+
+        let is_platform_a = q_platforms.contains(pair.entity_a);
+        let is_platform_b = q_platforms.contains(pair.entity_b);
+        if (!is_platform_a && !is_platform_b) || decisions.contains_key(&key) {
+            continue;
+        }
+
+        let blocked = if previous_passing.contains(&key) {
+            false
+        } else if collision_response_data.previous_contacts.contains_key(&key) {
+            true
+        } else {
+            (!is_platform_a || is_blocked_by_platform(collision_info, pair.entity_a, pair.entity_b, &q_platforms, &q_rigidbodies))
+                && (!is_platform_b || is_blocked_by_platform(collision_info, pair.entity_b, pair.entity_a, &q_platforms, &q_rigidbodies))
+        };
+        decisions.insert(key, blocked);
+    }
+
+    collision_response_data.passing_pairs = decisions.iter()
+        .filter(|(_, &blocked)| !blocked)
+        .map(|(&key, _)| key)
+        .collect::<HashSet<_>>();
+}
@@ -11,7 +11,8 @@ use crate::{
         systems,
     }
 };
-use crate::collision_plugin::{broad_phase, collision_response, debug, narrow_phase, rendering, TIMESTEP};
+use crate::collision_plugin::{broad_phase, ccd, collision_response, debug, events, narrow_phase, one_way_platform, rendering, TIMESTEP};
+use crate::collision_plugin::collision_layers::PairExclusions;
 use crate::collision_plugin::rendering::LineBatches;
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
@@ -42,6 +43,11 @@ impl Plugin for CollisionPlugin {
         app.init_resource::<NarrowPhaseData>();
         app.init_resource::<CollisionResponseData>();
         app.init_resource::<LineBatches>();
+        app.init_resource::<PairExclusions>();
+        // Events
+        app.add_event::<events::CollisionStarted>();
+        app.add_event::<events::CollisionPersisted>();
+        app.add_event::<events::CollisionEnded>();
         // Stages
         app.add_stage_after(
             CoreStage::PreUpdate,
@@ -100,11 +106,15 @@ impl CollisionPlugin {
         match stage {
             CollisionStage::PreSyncData => {
                 SystemSet::new()
+                    .with_system(systems::world_polygon_insert_system)
             }
             CollisionStage::SyncData => {
                 SystemSet::new()
                     .with_system(systems::refresh_entities)
                     .with_system(systems::aabb_update_system)
+                    .with_system(systems::world_polygon_update_system)
+                    .with_system(systems::heightfield_aabb_update_system)
+                    .with_system(systems::pair_exclusions_cleanup_system)
                     .with_system(clear_data)
             }
             CollisionStage::BroadPhase => {
@@ -117,7 +127,9 @@ impl CollisionPlugin {
             }
             CollisionStage::CollisionResponse => {
                 SystemSet::new()
+                    .with_system(one_way_platform::one_way_platform_system.before(collision_response::systems::collision_response))
                     .with_system(collision_response::systems::collision_response)
+                    .with_system(events::send_collision_events.after(collision_response::systems::collision_response))
             }
             CollisionStage::PostUpdate => {
                 SystemSet::new()
@@ -126,6 +138,7 @@ impl CollisionPlugin {
                     .with_system(rendering::render_lines)
                     .with_system(debug::systems::refresh_polygon_lines)
                     .with_system(systems::update_rigidbodies)
+                    .with_system(ccd::continuous_collision_system.after(systems::update_rigidbodies))
                 // .with_system(print_debug)
             }
         }
@@ -149,7 +162,6 @@ fn clear_data(
     narrow_phase_data.collision_infos.clear();
 
     narrow_phase_data.collided_entities.clear();
-    broad_phase_data.collision_pairs.clear();
 
     narrow_phase_data.time = Duration::default();
     broad_phase_data.time = Duration::default();
@@ -1,23 +1,150 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
 use bevy::prelude::*;
 
+use crate::collision_plugin::helpers::Cross;
 use crate::transform2d::Transform2d;
 
-#[derive(Component, Default, Reflect)]
+/// Points closer than this distance are merged into a single vertex
+const MERGE_DISTANCE: f32 = 1e-3;
+/// Relative tolerance used to decide if three consecutive points are collinear
+const COLLINEAR_TOLERANCE: f32 = 1e-5;
+/// Number of segments used to draw each rounded corner
+const ROUNDED_CORNER_SEGMENTS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PolygonError {
+    /// Less than 3 points were given
+    TooFewVertices(usize),
+    /// Less than 3 vertices remain once duplicates and collinear points are removed
+    Degenerate,
+    /// Every point lies on the same line
+    ZeroArea,
+    /// An edge goes straight back along the previous one, making a spike
+    ReversedEdge,
+    NonConvex,
+}
+
+impl Display for PolygonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolygonError::TooFewVertices(count) => write!(f, "polygon needs at least 3 vertices, got {}", count),
+            PolygonError::Degenerate => write!(f, "polygon has less than 3 distinct non collinear vertices"),
+            PolygonError::ZeroArea => write!(f, "polygon has zero area"),
+            PolygonError::ReversedEdge => write!(f, "polygon has an edge going back on the previous one"),
+            PolygonError::NonConvex => write!(f, "polygon is not convex"),
+        }
+    }
+}
+
+impl Error for PolygonError {}
+
+#[derive(Component, Default, Reflect, Clone, Debug)]
 #[reflect(Component)]
 pub struct PolygonComponent
 {
-    pub points: Vec<Vec2>,
+    /// Validated by [`PolygonComponent::try_new`], read with [`PolygonComponent::points`]
+    points: Vec<Vec2>,
     pub collided: bool,
+    /// Collision skin around the core shape, in local space. The polygon behaves as if its
+    /// outline was inflated by this margin with rounded corners.
+    pub radius: f32,
 }
 
 impl PolygonComponent {
+    /// Checked wrapper around [`PolygonComponent::try_new`], panics if the points are not a valid polygon
     pub fn new(points: Vec<Vec2>) -> Self
     {
-        PolygonComponent {
+        match Self::try_new(points) {
+            Ok(polygon) => polygon,
+            Err(error) => panic!("Invalid polygon: {}", error),
+        }
+    }
+
+    /// Validate and normalize the points into a convex counter-clockwise polygon.
+    /// Near-duplicate points are merged and collinear points are dropped.
+    pub fn try_new(points: Vec<Vec2>) -> Result<Self, PolygonError>
+    {
+        if points.len() < 3 {
+            return Err(PolygonError::TooFewVertices(points.len()));
+        }
+
+        let mut points = merge_duplicates(points);
+        if points.len() < 3 {
+            return Err(PolygonError::Degenerate);
+        }
+
+        // Compared to the polygon's own size, so the result doesn't depend on where it is placed
+        let area = signed_area(&points);
+        let extent = get_extent(&points);
+        if area.abs() <= COLLINEAR_TOLERANCE * extent * extent {
+            return Err(PolygonError::ZeroArea);
+        }
+        if area < 0f32 {
+            points.reverse();
+        }
+
+        remove_collinear(&mut points)?;
+        if points.len() < 3 {
+            return Err(PolygonError::Degenerate);
+        }
+
+        if !is_convex(&points) {
+            return Err(PolygonError::NonConvex);
+        }
+
+        return Ok(PolygonComponent {
             points,
             collided: false,
+            radius: 0f32,
+        });
+    }
+
+    /// Build the convex hull of a point cloud (monotone chain). If `max_vertices` is set, the hull is
+    /// simplified by repeatedly removing the vertex whose removal loses the least area.
+    pub fn from_convex_hull(points: &[Vec2], max_vertices: Option<usize>) -> Result<Self, PolygonError>
+    {
+        if points.len() < 3 {
+            return Err(PolygonError::TooFewVertices(points.len()));
+        }
+
+        let mut hull = convex_hull(points);
+        match hull.len() {
+            0 | 1 => return Err(PolygonError::Degenerate),
+            2 => return Err(PolygonError::ZeroArea),
+            _ => {}
+        }
+
+        if let Some(max_vertices) = max_vertices {
+            let max_vertices = max_vertices.max(3);
+            while hull.len() > max_vertices {
+                let (index, _) = (0..hull.len())
+                    .map(|i| {
+                        let prev = hull[(i + hull.len() - 1) % hull.len()];
+                        let next = hull[(i + 1) % hull.len()];
+                        (i, (hull[i] - prev).cross_vec(next - prev).abs())
+                    })
+                    .min_by(|(_, lhs), (_, rhs)| lhs.total_cmp(rhs))
+                    .unwrap();
+                hull.remove(index);
+            }
         }
+
+        return Self::try_new(hull);
+    }
+
+    pub fn points(&self) -> &[Vec2] {
+        return &self.points;
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> Self
+    {
+        assert!(radius >= 0f32, "Polygon radius must be positive, got {}", radius);
+        self.radius = radius;
+        self
     }
+
     pub fn get_transformed_points(&self, transform: &Transform2d) -> Vec<Vec2> {
         return self.points
             .iter()
@@ -39,34 +166,441 @@ impl PolygonComponent {
             .collect();
     }
 
-    pub fn is_point_inside(&self, transform: &Transform2d, test_point: &Vec2) -> bool {
-        let mut pos = false;
-        let mut neg = false;
+    /// Outline of the polygon including its rounded corners, in world space
+    pub fn get_outline_points(&self, transform: &Transform2d) -> Vec<Vec2> {
+        if self.radius <= 0f32 {
+            return self.get_transformed_points(transform);
+        }
+
+        let count = self.points.len();
+        let mut outline = Vec::with_capacity(count * (ROUNDED_CORNER_SEGMENTS + 1));
+        for i in 0..count {
+            let prev = self.points[(i + count - 1) % count];
+            let curr = self.points[i];
+            let next = self.points[(i + 1) % count];
 
-        let t_points = self.get_transformed_points(transform);
-        for i in 0..self.points.len() {
-            if t_points[i] == *test_point {
-                return true;
+            // Points are counter-clockwise, outward normals are on the right of the edges
+            let start_angle = (prev.x - curr.x).atan2(curr.y - prev.y);
+            let mut end_angle = (curr.x - next.x).atan2(next.y - curr.y);
+            if end_angle < start_angle {
+                end_angle += std::f32::consts::TAU;
             }
 
-            let point_1 = &t_points[i];
-            let point_2 = &t_points[(i + 1) % t_points.len()];
+            for step in 0..=ROUNDED_CORNER_SEGMENTS {
+                let angle = start_angle + (end_angle - start_angle) * step as f32 / ROUNDED_CORNER_SEGMENTS as f32;
+                outline.push(transform.transform_point(curr + Vec2::from_angle(angle) * self.radius));
+            }
+        }
+
+        return outline;
+    }
+
+    /// Whether the world space point is inside the polygon, tested in local space.
+    /// Entities with a cached WorldPolygon can test against it directly
+    pub fn is_point_inside(&self, transform: &Transform2d, test_point: &Vec2) -> bool {
+        let local_point = transform.inv_transform_point(*test_point) / transform.scale;
+        return is_point_inside(&self.points, self.radius, local_point);
+    }
+}
+
+/// World space vertices and outward edge normals of a polygon, refreshed once per step alongside its AABB
+/// so the narrow phase doesn't transform the points again for every pair and every support query
+#[derive(Component, Default, Clone, Debug)]
+pub struct WorldPolygon
+{
+    pub points: Vec<Vec2>,
+    /// Unit normal of the edge going from `points[i]` to `points[i + 1]`, zero for degenerate edges
+    pub normals: Vec<Vec2>,
+    /// Collision skin around the points, scaled to world space
+    pub radius: f32,
+}
+
+impl WorldPolygon {
+    pub fn new(polygon: &PolygonComponent, transform: &Transform2d) -> Self {
+        let mut world_polygon = Self::default();
+        world_polygon.update(polygon, transform);
+        return world_polygon;
+    }
+
+    /// Polygon made of the given counter-clockwise world space points, which are not validated.
+    /// It may also be a single point or a segment
+    pub fn from_points(points: Vec<Vec2>, radius: f32) -> Self {
+        let mut world_polygon = Self {
+            points,
+            normals: vec!(),
+            radius,
+        };
+        world_polygon.update_normals();
+        return world_polygon;
+    }
+
+    /// Transform the points again, reusing the allocations
+    pub fn update(&mut self, polygon: &PolygonComponent, transform: &Transform2d) {
+        self.points.clear();
+        self.points.extend(polygon.points.iter().map(|&p| transform.transform_point(p)));
+        self.radius = polygon.radius * transform.scale;
+        self.update_normals();
+    }
+
+    fn update_normals(&mut self) {
+        let count = self.points.len();
+        self.normals.clear();
+        self.normals.extend((0..count).map(|i| {
+            // Points are counter-clockwise, outward normals are on the right of the edges
+            let edge = self.points[(i + 1) % count] - self.points[i];
+            return Vec2::new(edge.y, -edge.x).normalize_or_zero();
+        }));
+    }
 
-            let a = *test_point - *point_1;
-            let b = *point_2 - *point_1;
-            let d = a.extend(0f32).cross(b.extend(0f32)).z;
+    pub fn translate(&mut self, offset: Vec2) {
+        for point in &mut self.points {
+            *point += offset;
+        }
+    }
 
-            if d > 0f32 {
-                pos = true;
-            } else if d < 0f32 {
-                neg = true;
+    /// Index of the vertex furthest along `direction`, the rounded skin excluded
+    pub fn find_furthest_index(&self, direction: Vec2) -> usize {
+        let mut furthest = 0;
+        let mut furthest_dot = f32::NEG_INFINITY;
+        for (index, point) in self.points.iter().enumerate() {
+            let dot = point.dot(direction);
+            if dot >= furthest_dot {
+                furthest = index;
+                furthest_dot = dot;
             }
+        }
+        return furthest;
+    }
+
+    pub fn find_furthest_vertex(&self, direction: Vec2) -> Vec2 {
+        return self.points[self.find_furthest_index(direction)];
+    }
+
+    /// Support point along `direction`, including the rounded skin
+    pub fn find_furthest_point(&self, direction: Vec2) -> Vec2 {
+        let furthest = self.find_furthest_vertex(direction);
+        if self.radius <= 0f32 {
+            return furthest;
+        }
+        return furthest + direction.normalize_or_zero() * self.radius;
+    }
+
+    /// World space bounds, including the rounded skin
+    pub fn get_bounds(&self) -> (Vec2, Vec2) {
+        let margin = Vec2::splat(self.radius);
+        let min = self.points.iter().fold(Vec2::splat(f32::INFINITY), |acc, &p| acc.min(p)) - margin;
+        let max = self.points.iter().fold(Vec2::splat(f32::NEG_INFINITY), |acc, &p| acc.max(p)) + margin;
+        return (min, max);
+    }
+
+    pub fn is_point_inside(&self, test_point: Vec2) -> bool {
+        return is_point_inside(&self.points, self.radius, test_point);
+    }
+}
+
+// Point in the convex polygon made of `points`, or within `radius` of its edges
+fn is_point_inside(points: &[Vec2], radius: f32, test_point: Vec2) -> bool {
+    if radius > 0f32 && !is_point_inside_core(points, test_point) {
+        let count = points.len();
+        return (0..count).any(|i| {
+            let a = points[i];
+            let b = points[(i + 1) % count];
+            let ab = b - a;
+            let t = ((test_point - a).dot(ab) / ab.length_squared()).clamp(0f32, 1f32);
+            return (a + ab * t).distance_squared(test_point) <= radius * radius;
+        });
+    }
+
+    return is_point_inside_core(points, test_point);
+}
+
+fn is_point_inside_core(points: &[Vec2], test_point: Vec2) -> bool {
+    let mut pos = false;
+    let mut neg = false;
+
+    let count = points.len();
+    for i in 0..count {
+        if points[i] == test_point {
+            return true;
+        }
 
-            if pos && neg {
-                return false;
+        let point_1 = &points[i];
+        let point_2 = &points[(i + 1) % count];
+
+        let a = test_point - *point_1;
+        let b = *point_2 - *point_1;
+        let d = a.extend(0f32).cross(b.extend(0f32)).z;
+
+        if d > 0f32 {
+            pos = true;
+        } else if d < 0f32 {
+            neg = true;
+        }
+
+        if pos && neg {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+fn merge_duplicates(points: Vec<Vec2>) -> Vec<Vec2>
+{
+    let mut merged: Vec<Vec2> = Vec::with_capacity(points.len());
+    for point in points {
+        if merged.last().map_or(true, |last| last.distance(point) > MERGE_DISTANCE) {
+            merged.push(point);
+        }
+    }
+
+    while merged.len() > 1 && merged[0].distance(*merged.last().unwrap()) <= MERGE_DISTANCE {
+        merged.pop();
+    }
+
+    return merged;
+}
+
+// Andrew's monotone chain, returns the hull in counter-clockwise order without collinear points
+fn convex_hull(points: &[Vec2]) -> Vec<Vec2>
+{
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|lhs, rhs| lhs.x.total_cmp(&rhs.x).then(lhs.y.total_cmp(&rhs.y)));
+    sorted.dedup();
+
+    if sorted.len() < 3 {
+        return sorted;
+    }
+
+    let mut hull: Vec<Vec2> = Vec::with_capacity(sorted.len() * 2);
+
+    // Lower hull
+    for &point in &sorted {
+        while hull.len() >= 2 && (hull[hull.len() - 1] - hull[hull.len() - 2]).cross_vec(point - hull[hull.len() - 2]) <= 0f32 {
+            hull.pop();
+        }
+        hull.push(point);
+    }
+
+    // Upper hull
+    let lower_len = hull.len() + 1;
+    for &point in sorted.iter().rev().skip(1) {
+        while hull.len() >= lower_len && (hull[hull.len() - 1] - hull[hull.len() - 2]).cross_vec(point - hull[hull.len() - 2]) <= 0f32 {
+            hull.pop();
+        }
+        hull.push(point);
+    }
+
+    // Last point is the same as the first one
+    hull.pop();
+    return hull;
+}
+
+// Largest side of the bounding box of the points
+fn get_extent(points: &[Vec2]) -> f32
+{
+    let min = points.iter().fold(Vec2::splat(f32::INFINITY), |acc, &p| acc.min(p));
+    let max = points.iter().fold(Vec2::splat(f32::NEG_INFINITY), |acc, &p| acc.max(p));
+    return (max - min).max_element();
+}
+
+// Relative to the first point, so far away polygons don't lose precision
+fn signed_area(points: &[Vec2]) -> f32
+{
+    let origin = points[0];
+    let mut area = 0f32;
+    for i in 0..points.len() {
+        let j = (i + 1) % points.len();
+        area += (points[i] - origin).cross_vec(points[j] - origin);
+    }
+    return area * 0.5f32;
+}
+
+// Drop vertices lying on the segment formed by their neighbours.
+// Spikes going back on themselves are collinear too, but they are rejected instead of hidden
+fn remove_collinear(points: &mut Vec<Vec2>) -> Result<(), PolygonError>
+{
+    let mut i = 0;
+    while points.len() >= 3 && i < points.len() {
+        let prev = points[(i + points.len() - 1) % points.len()];
+        let curr = points[i];
+        let next = points[(i + 1) % points.len()];
+
+        let e1 = curr - prev;
+        let e2 = next - curr;
+
+        if e1.cross_vec(e2).abs() <= COLLINEAR_TOLERANCE * e1.length() * e2.length() {
+            if e1.dot(e2) < 0f32 {
+                return Err(PolygonError::ReversedEdge);
             }
+            points.remove(i);
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+
+    return Ok(());
+}
+
+// Expects counter-clockwise points: every turn must be to the left and the boundary must wind only once
+fn is_convex(points: &[Vec2]) -> bool
+{
+    let mut total_angle = 0f32;
+    for i in 0..points.len() {
+        let prev = points[(i + points.len() - 1) % points.len()];
+        let curr = points[i];
+        let next = points[(i + 1) % points.len()];
+
+        let e1 = curr - prev;
+        let e2 = next - curr;
+
+        let cross = e1.cross_vec(e2);
+        if cross <= 0f32 {
+            return false;
+        }
+        total_angle += cross.atan2(e1.dot(e2));
+    }
+
+    return total_angle <= std::f32::consts::TAU + 1e-3;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(half_size: f32, center: Vec2) -> Vec<Vec2> {
+        return vec!(
+            center + Vec2::new(-half_size, -half_size),
+            center + Vec2::new(half_size, -half_size),
+            center + Vec2::new(half_size, half_size),
+            center + Vec2::new(-half_size, half_size),
+        );
+    }
+
+    #[test]
+    fn try_new_rejects_too_few_vertices() {
+        let result = PolygonComponent::try_new(vec!(Vec2::ZERO, Vec2::X));
+        assert_eq!(result.unwrap_err(), PolygonError::TooFewVertices(2));
+    }
+
+    #[test]
+    fn try_new_rejects_duplicates() {
+        let result = PolygonComponent::try_new(vec!(Vec2::ZERO, Vec2::X, Vec2::X * 1.0001f32, Vec2::ZERO));
+        assert_eq!(result.unwrap_err(), PolygonError::Degenerate);
+    }
+
+    #[test]
+    fn try_new_rejects_zero_area() {
+        let result = PolygonComponent::try_new(vec!(Vec2::ZERO, Vec2::X, Vec2::X * 2f32));
+        assert_eq!(result.unwrap_err(), PolygonError::ZeroArea);
+    }
+
+    #[test]
+    fn try_new_zero_area_does_not_depend_on_position() {
+        // Thin but valid triangle, close to and far from the origin
+        let thin = [Vec2::ZERO, Vec2::new(1f32, 0f32), Vec2::new(0.5f32, 0.01f32)];
+        for offset in [Vec2::ZERO, Vec2::splat(1e4f32)] {
+            let points = thin.iter().map(|&p| p + offset).collect::<Vec<_>>();
+            assert!(PolygonComponent::try_new(points).is_ok(), "offset {}", offset);
+        }
+    }
+
+    #[test]
+    fn try_new_rejects_reversed_edge() {
+        let points = vec!(
+            Vec2::new(0f32, 0f32),
+            Vec2::new(1f32, 0f32),
+            Vec2::new(1f32, 1f32),
+            Vec2::new(1f32, 2f32),
+            Vec2::new(1f32, 1.5f32),
+            Vec2::new(0f32, 1f32),
+        );
+        assert_eq!(PolygonComponent::try_new(points).unwrap_err(), PolygonError::ReversedEdge);
+    }
+
+    #[test]
+    fn try_new_rejects_non_convex() {
+        let points = vec!(
+            Vec2::new(0f32, 0f32),
+            Vec2::new(2f32, 0f32),
+            Vec2::new(1f32, 0.5f32),
+            Vec2::new(2f32, 2f32),
+            Vec2::new(0f32, 2f32),
+        );
+        assert_eq!(PolygonComponent::try_new(points).unwrap_err(), PolygonError::NonConvex);
+    }
+
+    #[test]
+    fn try_new_normalizes_points() {
+        // Clockwise, with a collinear point in the middle of the bottom edge
+        let mut points = square(1f32, Vec2::ZERO);
+        points.insert(1, Vec2::new(0f32, -1f32));
+        points.reverse();
+
+        let polygon = PolygonComponent::try_new(points).unwrap();
+        assert_eq!(polygon.points().len(), 4);
+        assert!(signed_area(polygon.points()) > 0f32);
+    }
+
+    #[test]
+    fn from_convex_hull_drops_inner_points() {
+        let mut points = square(1f32, Vec2::ZERO);
+        points.extend([Vec2::ZERO, Vec2::new(0.5f32, -0.2f32), Vec2::new(1f32, 0f32)]);
+        fastrand::seed(3);
+        fastrand::shuffle(&mut points);
+
+        let polygon = PolygonComponent::from_convex_hull(&points, None).unwrap();
+        assert_eq!(polygon.points().len(), 4);
+        assert!((signed_area(polygon.points()) - 4f32).abs() < 1e-5);
+        for corner in square(1f32, Vec2::ZERO) {
+            assert!(polygon.points().contains(&corner));
         }
+    }
 
-        return true;
+    #[test]
+    fn from_convex_hull_simplifies_to_max_vertices() {
+        // Hexagon with one vertex barely sticking out, which is the cheapest to remove
+        let mut points = (0..6)
+            .map(|i| Vec2::from_angle(i as f32 * std::f32::consts::TAU / 6f32) * 10f32)
+            .collect::<Vec<_>>();
+        points[1] = (points[0] + points[2]) * 0.5f32 * 1.01f32;
+
+        let polygon = PolygonComponent::from_convex_hull(&points, Some(5)).unwrap();
+        assert_eq!(polygon.points().len(), 5);
+        assert!(!polygon.points().contains(&points[1]));
+
+        // Never below a triangle
+        let polygon = PolygonComponent::from_convex_hull(&points, Some(1)).unwrap();
+        assert_eq!(polygon.points().len(), 3);
+    }
+
+    #[test]
+    fn from_convex_hull_rejects_degenerate_clouds() {
+        assert_eq!(PolygonComponent::from_convex_hull(&[Vec2::ZERO, Vec2::X], None).unwrap_err(), PolygonError::TooFewVertices(2));
+        assert_eq!(PolygonComponent::from_convex_hull(&[Vec2::X; 4], None).unwrap_err(), PolygonError::Degenerate);
+        assert_eq!(PolygonComponent::from_convex_hull(&[Vec2::ZERO, Vec2::X, Vec2::X * 3f32], None).unwrap_err(), PolygonError::ZeroArea);
+    }
+
+    #[test]
+    fn is_point_inside_matches_world_polygon() {
+        let polygon = PolygonComponent::new(square(1f32, Vec2::ZERO)).with_radius(0.5f32);
+        let transform = Transform2d {
+            translation: Vec2::new(10f32, -4f32),
+            rotation: 0.6f32,
+            scale: 3f32,
+        };
+        let world_polygon = WorldPolygon::new(&polygon, &transform);
+
+        for x in -12..=12 {
+            for y in -12..=12 {
+                let point = transform.translation + Vec2::new(x as f32, y as f32) * 0.5f32;
+                assert_eq!(polygon.is_point_inside(&transform, &point), world_polygon.is_point_inside(point), "{:?}", point);
+            }
+        }
+        assert!(polygon.is_point_inside(&transform, &transform.translation));
+        // Inside the scaled rounded skin only
+        assert!(polygon.is_point_inside(&transform, &transform.transform_point(Vec2::new(1.4f32, 0f32))));
+        assert!(!polygon.is_point_inside(&transform, &transform.transform_point(Vec2::new(1.6f32, 0f32))));
     }
-}
\ No newline at end of file
+}
@@ -3,10 +3,11 @@ use std::f32::consts::PI;
 use bevy::input::mouse::MouseMotion;
 use bevy::prelude::*;
 
-use crate::collision_plugin::aabb::AABB;
 use crate::collision_plugin::plugin::CollisionStage;
 use crate::collision_plugin::polygon_component::PolygonComponent;
+use crate::collision_plugin::rendering::{LineBatch, LineBatches};
 use crate::collision_plugin::rigidbody::RigidBody2d;
+use crate::collision_plugin::spatial_query::{QueryFilter, SpatialQuery};
 use crate::MainCamera;
 use crate::transform2d::Transform2d;
 
@@ -20,41 +21,51 @@ pub struct EntityToMove;
 #[component(storage = "SparseSet")]
 pub struct EntityToRotate;
 
+fn get_cursor_world_position(camera: &Camera, camera_transform: &GlobalTransform, windows: &Windows) -> Option<Vec2>
+{
+    let wnd = windows.get_primary().unwrap();
+    let position = wnd.cursor_position()?;
+
+    let window_size = Vec2::new(wnd.width() as f32, wnd.height() as f32);
+    let ndc = (position / window_size) * 2.0 - Vec2::ONE;
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
+    let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
+
+    return Some(world_pos.truncate());
+}
+
 fn select_polygons(
     mut commands: Commands,
     q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-    mut q_polygons: Query<(Entity, &PolygonComponent, &AABB, &Transform2d, &mut RigidBody2d, Option<&EntityToRotate>, Option<&EntityToMove>)>,
+    q_polygons: Query<(Option<&EntityToRotate>, Option<&EntityToMove>), With<PolygonComponent>>,
+    q_selected: Query<(Entity, Option<&EntityToRotate>, Option<&EntityToMove>), Or<(With<EntityToRotate>, With<EntityToMove>)>>,
+    spatial_query: SpatialQuery,
     buttons: Res<Input<MouseButton>>,
     windows: Res<Windows>,
 )
 {
     let (camera, camera_transform) = q_camera.single();
-    let wnd = windows.get_primary().unwrap();
-
-    if let Some(_position) = wnd.cursor_position() {
-        let window_size = Vec2::new(wnd.width() as f32, wnd.height() as f32);
-        let ndc = (_position / window_size) * 2.0 - Vec2::ONE;
-        let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
-        let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
 
-        let world_pos: Vec2 = world_pos.truncate();
-
-        for (entity, _polygon, aabb, transform, rigidbody, entity_to_rotate, entity_to_move) in q_polygons.iter_mut() {
-            // let is_point_inside = aabb.is_point_inside(world_pos, transform);
-            let is_point_inside = _polygon.is_point_inside(transform, &world_pos);
-            if buttons.just_pressed(MouseButton::Left) && entity_to_move.is_none() && is_point_inside {
+    if let Some(world_pos) = get_cursor_world_position(camera, camera_transform, &windows) {
+        for entity in spatial_query.entities_at_point(world_pos, &QueryFilter::default()) {
+            let Ok((entity_to_rotate, entity_to_move)) = q_polygons.get(entity) else { continue; };
+            if buttons.just_pressed(MouseButton::Left) && entity_to_move.is_none() {
                 commands.entity(entity).insert(EntityToMove);
-            } else if buttons.just_released(MouseButton::Left) && entity_to_move.is_some() {
-                commands.entity(entity).remove::<EntityToMove>();
             }
-
-            if buttons.just_pressed(MouseButton::Right) && entity_to_rotate.is_none() && is_point_inside {
+            if buttons.just_pressed(MouseButton::Right) && entity_to_rotate.is_none() {
                 commands.entity(entity).insert(EntityToRotate);
-            } else if buttons.just_released(MouseButton::Right) && entity_to_rotate.is_some() {
-                commands.entity(entity).remove::<EntityToRotate>();
             }
         }
     }
+
+    for (entity, entity_to_rotate, entity_to_move) in q_selected.iter() {
+        if buttons.just_released(MouseButton::Left) && entity_to_move.is_some() {
+            commands.entity(entity).remove::<EntityToMove>();
+        }
+        if buttons.just_released(MouseButton::Right) && entity_to_rotate.is_some() {
+            commands.entity(entity).remove::<EntityToRotate>();
+        }
+    }
 }
 
 fn move_polygon(
@@ -90,7 +101,7 @@ fn move_polygon(
     {
         if entity_to_move.is_some()
         {
-            if rigibody.is_kinematic {
+            if rigibody.is_immovable() {
                 transform.translation += delta;
             } else {
                 rigibody.linear_speed += delta;
@@ -98,7 +109,7 @@ fn move_polygon(
         }
         if entity_to_rotate.is_some()
         {
-            if rigibody.is_kinematic
+            if rigibody.is_immovable()
             {
                 transform.rotation += delta.x.to_radians() % (PI * 2.0);
             } else {
@@ -123,12 +134,81 @@ fn auto_move_polygon(
     }
 }
 
+// Cast a ray (R) or a square (T) from the center of the screen toward the cursor,
+// drawing the path up to the first hit and the normal there
+fn cast_to_cursor(
+    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    spatial_query: SpatialQuery,
+    mut line_batches: ResMut<LineBatches>,
+    kb_buttons: Res<Input<KeyCode>>,
+    windows: Res<Windows>,
+)
+{
+    const MAX_DISTANCE: f32 = 10000f32;
+    const NORMAL_LENGTH: f32 = 20f32;
+    const SQUARE_HALF_SIZE: f32 = 20f32;
+
+    let cast_ray = kb_buttons.pressed(KeyCode::R);
+    let cast_shape = kb_buttons.pressed(KeyCode::T);
+    if !cast_ray && !cast_shape {
+        return;
+    }
+
+    let (camera, camera_transform) = q_camera.single();
+    let Some(world_pos) = get_cursor_world_position(camera, camera_transform, &windows) else { return; };
+
+    let origin = camera_transform.translation().truncate();
+    let direction = (world_pos - origin).normalize_or_zero();
+    if direction == Vec2::ZERO {
+        return;
+    }
+
+    let mut batch = LineBatch::new(Color::WHITE);
+    let mut end = origin + direction * MAX_DISTANCE;
+
+    if cast_ray {
+        if let Some(hit) = spatial_query.cast_ray(origin, direction, MAX_DISTANCE, &QueryFilter::default()) {
+            end = hit.point;
+            batch.try_push_vertices(&[hit.point.extend(0f32), (hit.point + hit.normal * NORMAL_LENGTH).extend(0f32)]);
+        }
+    } else {
+        let square = PolygonComponent::new(vec!(
+            Vec2::new(-SQUARE_HALF_SIZE, -SQUARE_HALF_SIZE),
+            Vec2::new(SQUARE_HALF_SIZE, -SQUARE_HALF_SIZE),
+            Vec2::new(SQUARE_HALF_SIZE, SQUARE_HALF_SIZE),
+            Vec2::new(-SQUARE_HALF_SIZE, SQUARE_HALF_SIZE),
+        ));
+        let start_transform = Transform2d {
+            translation: origin,
+            rotation: 0f32,
+            scale: 1f32,
+        };
+
+        if let Some(hit) = spatial_query.cast_shape(&square, &start_transform, direction, MAX_DISTANCE, &QueryFilter::default()) {
+            end = origin + direction * hit.toi;
+            batch.try_push_vertices(&[hit.point.extend(0f32), (hit.point + hit.normal * NORMAL_LENGTH).extend(0f32)]);
+
+            let hit_transform = Transform2d {
+                translation: end,
+                ..start_transform
+            };
+            let mut outline = square.get_transformed_points(&hit_transform);
+            outline.push(outline[0]);
+            batch.try_push_vertices(&outline.iter().map(|point| point.extend(0f32)).collect::<Vec<_>>());
+        }
+    }
+
+    batch.try_push_vertices(&[origin.extend(0f32), end.extend(0f32)]);
+    line_batches.batches.push(batch);
+}
+
 impl Plugin for DrawPolygonPlugin {
     fn build(&self, app: &mut App) {
         app
             .add_stage_before(CollisionStage::SyncData, "Salut", SystemStage::parallel())
             .add_system_to_stage("Salut", select_polygons)
             .add_system_to_stage("Salut", move_polygon)
-            .add_system_to_stage("Salut", auto_move_polygon);
+            .add_system_to_stage("Salut", auto_move_polygon)
+            .add_system_to_stage("Salut", cast_to_cursor);
     }
 }
\ No newline at end of file
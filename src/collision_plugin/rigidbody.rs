@@ -1,10 +1,20 @@
 use bevy::prelude::*;
 
+/// Static bodies never move and are kept out of the main broad phase structure,
+/// kinematic bodies are moved by hand, and both have an infinite mass.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum BodyType {
+    Static,
+    Kinematic,
+    #[default]
+    Dynamic,
+}
+
 #[derive(Component)]
 pub struct RigidBody2d {
     pub mass: f32,
     pub friction: f32,
-    pub is_kinematic: bool,
+    pub body_type: BodyType,
 
     pub linear_speed: Vec2,
     pub angular_speed: f32,
@@ -14,15 +24,20 @@ pub struct RigidBody2d {
 }
 
 impl RigidBody2d {
+    /// True for static and kinematic bodies, which are not moved by collisions
+    pub fn is_immovable(&self) -> bool {
+        return self.body_type != BodyType::Dynamic;
+    }
+
     pub(crate) fn get_mass(&self) -> f32 {
-        if self.is_kinematic {
+        if self.is_immovable() {
             return f32::MAX;
         }
         return self.mass;
     }
 
     pub(crate) fn get_inv_mass(&self) -> f32 {
-        if self.is_kinematic {
+        if self.is_immovable() {
             return  0f32;
         }
         return 1f32/self.mass
@@ -34,7 +49,7 @@ impl Default for RigidBody2d {
         RigidBody2d {
             mass: 10f32,
             friction: 0.9f32,
-            is_kinematic: false,
+            body_type: BodyType::Dynamic,
             linear_speed: Vec2::ZERO,
             angular_speed: 0f32,
             // linear_acceleration: Vec2::new(0f32, -9.8f32),
@@ -0,0 +1,273 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::collision_plugin::aabb::{AABB, check_bounds_overlap, check_ray_overlap};
+use crate::collision_plugin::collision_layers::CollisionLayers;
+use crate::collision_plugin::ColliderFilter;
+use crate::collision_plugin::config::{BroadPhaseType, CollisionConfig};
+use crate::collision_plugin::data_structs::BroadPhaseData;
+use crate::collision_plugin::heightfield_component::HeightfieldComponent;
+use crate::collision_plugin::narrow_phase::gjk;
+use crate::collision_plugin::narrow_phase::raycast::{self, RayIntersection};
+use crate::collision_plugin::narrow_phase::time_of_impact::{self, TimeOfImpact};
+use crate::collision_plugin::polygon_component::{PolygonComponent, WorldPolygon};
+use crate::transform2d::Transform2d;
+
+pub type SpatialQueryColliders<'w, 's> = Query<
+    'w, 's,
+    (
+        Entity,
+        &'static Transform2d,
+        &'static AABB,
+        Option<&'static WorldPolygon>,
+        Option<&'static HeightfieldComponent>,
+        Option<&'static CollisionLayers>,
+    ),
+    ColliderFilter
+>;
+
+/// Selects the colliders a query can hit
+#[derive(Clone, Default, Debug)]
+pub struct QueryFilter
+{
+    /// Layers of the query, tested against the colliders' layers as if it was a collider itself
+    pub layers: CollisionLayers,
+    /// Colliders that are never hit, such as the one casting the ray
+    pub excluded_entities: Vec<Entity>,
+}
+
+impl QueryFilter {
+    pub fn new(layers: CollisionLayers) -> Self {
+        return Self {
+            layers,
+            excluded_entities: vec!(),
+        };
+    }
+
+    pub fn exclude(mut self, entity: Entity) -> Self {
+        self.excluded_entities.push(entity);
+        return self;
+    }
+
+    fn accepts(&self, entity: Entity, layers: Option<&CollisionLayers>) -> bool {
+        return !self.excluded_entities.contains(&entity)
+            && self.layers.interacts_with(&layers.cloned().unwrap_or_default());
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub entity: Entity,
+    pub point: Vec2,
+    /// Surface normal at the hit point, facing the ray
+    pub normal: Vec2,
+    /// Part of the ray travelled before the hit, between 0 and 1: `point = origin + direction * max_toi * fraction`
+    pub fraction: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShapeHit {
+    pub entity: Entity,
+    /// The shape touches the collider once moved to `start + velocity * toi`
+    pub toi: f32,
+    /// Contact point on the surface of the collider
+    pub point: Vec2,
+    /// Surface normal of the collider at the contact point, facing the shape
+    pub normal: Vec2,
+}
+
+/// Queries against the colliders of the world, culled by the structures of the active broad phase.
+/// Results reflect the colliders as they were during the last broad phase.
+#[derive(SystemParam)]
+pub struct SpatialQuery<'w, 's> {
+    colliders: SpatialQueryColliders<'w, 's>,
+    broad_phase_data: Res<'w, BroadPhaseData>,
+    config: Res<'w, CollisionConfig>,
+}
+
+impl<'w, 's> SpatialQuery<'w, 's> {
+    /// Closest collider hit by the ray `origin + direction * t`, t in [0, max_toi]
+    pub fn cast_ray(&self, origin: Vec2, direction: Vec2, max_toi: f32, filter: &QueryFilter) -> Option<RayHit> {
+        let mut candidates = self.get_ray_candidates(origin, direction, max_toi, filter);
+        candidates.sort_by(|(_, lhs), (_, rhs)| lhs.total_cmp(rhs));
+
+        let mut closest: Option<RayHit> = None;
+        for (entity, toi_bounds) in candidates {
+            // Candidates are sorted by the toi of their bounds, no further one can be closer
+            if closest.map_or(false, |hit| toi_bounds > hit.fraction * max_toi) {
+                break;
+            }
+
+            if let Some(hit) = self.cast_ray_entity(entity, origin, direction, max_toi) {
+                if closest.map_or(true, |closest| hit.fraction < closest.fraction) {
+                    closest = Some(hit);
+                }
+            }
+        }
+
+        return closest;
+    }
+
+    /// Every collider hit by the ray `origin + direction * t`, t in [0, max_toi], sorted from the closest
+    pub fn cast_ray_all(&self, origin: Vec2, direction: Vec2, max_toi: f32, filter: &QueryFilter) -> Vec<RayHit> {
+        let mut hits = self.get_ray_candidates(origin, direction, max_toi, filter).into_iter()
+            .filter_map(|(entity, _)| self.cast_ray_entity(entity, origin, direction, max_toi))
+            .collect::<Vec<_>>();
+
+        hits.sort_by(|lhs, rhs| lhs.fraction.total_cmp(&rhs.fraction));
+        return hits;
+    }
+
+    /// First collider touched by the shape moving from `start_transform` along `velocity * t`, t in [0, max_toi].
+    /// The rotation of the shape doesn't change during the cast, and colliders it already overlaps are hit at a toi of 0.
+    pub fn cast_shape(&self, shape: &PolygonComponent, start_transform: &Transform2d, velocity: Vec2, max_toi: f32, filter: &QueryFilter) -> Option<ShapeHit> {
+        let shape = WorldPolygon::new(shape, start_transform);
+        let (min, max) = shape.get_bounds();
+        let displacement = velocity * max_toi;
+        let min = min + displacement.min(Vec2::ZERO);
+        let max = max + displacement.max(Vec2::ZERO);
+
+        let mut closest: Option<ShapeHit> = None;
+        for entity in self.get_candidates(min, max, filter) {
+            let (_entity, transform, _aabb, polygon, heightfield, _layers) = self.colliders.get(entity).unwrap();
+            let max_toi = closest.map_or(max_toi, |hit| hit.toi);
+
+            let time_of_impact = if let Some(polygon) = polygon {
+                time_of_impact::compute_time_of_impact(&shape, velocity, polygon, Vec2::ZERO, max_toi)
+            } else if let Some(heightfield) = heightfield {
+                time_of_impact::compute_time_of_impact_heightfield(&shape, velocity, heightfield, transform, max_toi)
+            } else {
+                None
+            };
+
+            if let Some(TimeOfImpact { toi, point, normal, .. }) = time_of_impact {
+                if closest.map_or(true, |hit| toi < hit.toi) {
+                    closest = Some(ShapeHit { entity, toi, point, normal });
+                }
+            }
+        }
+
+        return closest;
+    }
+
+    /// Colliders containing the point, including their rounded skin and boundary
+    pub fn entities_at_point(&self, point: Vec2, filter: &QueryFilter) -> Vec<Entity> {
+        let shape = WorldPolygon::from_points(vec!(point), 0f32);
+
+        return self.get_candidates(point, point, filter).into_iter()
+            .filter(|&entity| self.intersects_entity(entity, &shape, point, point))
+            .collect();
+    }
+
+    /// Colliders overlapping the world space box, their shape and not only their bounds
+    pub fn entities_in_aabb(&self, min: Vec2, max: Vec2, filter: &QueryFilter) -> Vec<Entity> {
+        let shape = WorldPolygon::from_points(vec!(min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)), 0f32);
+
+        return self.get_candidates(min, max, filter).into_iter()
+            .filter(|&entity| self.intersects_entity(entity, &shape, min, max))
+            .collect();
+    }
+
+    /// Colliders overlapping or touching the shape placed at `transform`
+    pub fn entities_intersecting_shape(&self, shape: &PolygonComponent, transform: &Transform2d, filter: &QueryFilter) -> Vec<Entity> {
+        let shape = WorldPolygon::new(shape, transform);
+        let (min, max) = shape.get_bounds();
+
+        return self.get_candidates(min, max, filter).into_iter()
+            .filter(|&entity| self.intersects_entity(entity, &shape, min, max))
+            .collect();
+    }
+
+    // GJK finds no distance between shapes which overlap, only the heightfield cells under the shape's bounds are tested
+    fn intersects_entity(&self, entity: Entity, shape: &WorldPolygon, min: Vec2, max: Vec2) -> bool {
+        let Ok((_entity, transform, _aabb, polygon, heightfield, _layers)) = self.colliders.get(entity) else { return false; };
+
+        if let Some(polygon) = polygon {
+            return gjk::get_distance(shape, polygon).is_none();
+        }
+
+        let Some(heightfield) = heightfield else { return false; };
+        return heightfield.get_cell_range_in_aabb(transform, min, max)
+            .any(|cell| gjk::get_distance(shape, &heightfield.get_cell_world_polygon(cell, transform)).is_none());
+    }
+
+    fn cast_ray_entity(&self, entity: Entity, origin: Vec2, direction: Vec2, max_toi: f32) -> Option<RayHit> {
+        let (_entity, transform, _aabb, polygon, heightfield, _layers) = self.colliders.get(entity).ok()?;
+
+        let RayIntersection { toi, normal } = if let Some(polygon) = polygon {
+            raycast::cast_ray_polygon(polygon, origin, direction, max_toi)?
+        } else {
+            raycast::cast_ray_heightfield(heightfield?, transform, origin, direction, max_toi)?
+        };
+
+        return Some(RayHit {
+            entity,
+            point: origin + direction * toi,
+            normal,
+            fraction: if max_toi > 0f32 { toi / max_toi } else { 0f32 },
+        });
+    }
+
+    // Entities accepted by the filter whose tight bounds are crossed by the ray, with the toi at which it enters them
+    fn get_ray_candidates(&self, origin: Vec2, direction: Vec2, max_toi: f32, filter: &QueryFilter) -> Vec<(Entity, f32)> {
+        let end = origin + direction * max_toi;
+        let (min, max) = (origin.min(end), origin.max(end));
+
+        let data = &self.broad_phase_data;
+        let mut entities = match self.config.phase_config.broad_phase_type {
+            BroadPhaseType::SAP => data.sweep_and_prune.query(min, max),
+            BroadPhaseType::DynamicTree => data.dynamic_tree.ray_query(origin, direction, max_toi),
+            BroadPhaseType::Quadtree => data.quadtree.ray_query(origin, direction, max_toi),
+            // No persistent structure for the moving entities, test all the colliders
+            BroadPhaseType::Disabled | BroadPhaseType::Rough | BroadPhaseType::Grid => {
+                let entities = self.colliders.iter().map(|(entity, ..)| entity).collect();
+                return self.filter_ray_candidates(entities, origin, direction, max_toi, filter);
+            }
+        };
+        entities.append(&mut data.static_tree.ray_query(origin, direction, max_toi));
+
+        return self.filter_ray_candidates(entities, origin, direction, max_toi, filter);
+    }
+
+    // Entities accepted by the filter whose tight bounds overlap the given world space region
+    fn get_candidates(&self, min: Vec2, max: Vec2, filter: &QueryFilter) -> Vec<Entity> {
+        let data = &self.broad_phase_data;
+        let mut entities = match self.config.phase_config.broad_phase_type {
+            BroadPhaseType::SAP => data.sweep_and_prune.query(min, max),
+            BroadPhaseType::DynamicTree => data.dynamic_tree.query(min, max),
+            BroadPhaseType::Quadtree => data.quadtree.query(min, max),
+            // No persistent structure for the moving entities, test all the colliders
+            BroadPhaseType::Disabled | BroadPhaseType::Rough | BroadPhaseType::Grid => {
+                return self.colliders.iter()
+                    .map(|(entity, ..)| entity)
+                    .filter(|&entity| self.is_candidate_in_bounds(entity, min, max, filter))
+                    .collect();
+            }
+        };
+        entities.append(&mut data.static_tree.query(min, max));
+
+        return entities.into_iter()
+            .filter(|&entity| self.is_candidate_in_bounds(entity, min, max, filter))
+            .collect();
+    }
+
+    fn is_candidate_in_bounds(&self, entity: Entity, min: Vec2, max: Vec2, filter: &QueryFilter) -> bool {
+        let Ok((_entity, transform, aabb, _polygon, _heightfield, layers)) = self.colliders.get(entity) else { return false; };
+        return filter.accepts(entity, layers)
+            && check_bounds_overlap(transform.translate(aabb.min), transform.translate(aabb.max), min, max);
+    }
+
+    fn filter_ray_candidates(&self, entities: Vec<Entity>, origin: Vec2, direction: Vec2, max_toi: f32, filter: &QueryFilter) -> Vec<(Entity, f32)> {
+        return entities.into_iter()
+            .filter_map(|entity| {
+                let (_entity, transform, aabb, _polygon, _heightfield, layers) = self.colliders.get(entity).ok()?;
+                if !filter.accepts(entity, layers) {
+                    return None;
+                }
+
+                let toi = check_ray_overlap(origin, direction, max_toi, transform.translate(aabb.min), transform.translate(aabb.max))?;
+                return Some((entity, toi));
+            })
+            .collect();
+    }
+}
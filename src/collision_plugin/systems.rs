@@ -3,9 +3,13 @@ use bevy::prelude::*;
 use crate::{
     collision_plugin::{
         aabb::AABB,
+        ccd::Ccd,
+        ColliderFilter,
+        collision_layers::PairExclusions,
         config::{CollisionConfig},
+        heightfield_component::HeightfieldComponent,
         PhysicsAwake,
-        polygon_component::PolygonComponent,
+        polygon_component::{PolygonComponent, WorldPolygon},
         rigidbody::RigidBody2d,
     }
 };
@@ -18,7 +22,7 @@ pub(crate) fn startup_refresh_entities(
     entities: Query<Entity, With<PhysicsAwake>>,
 )
 {
-    broad_phase_data.sorted_entities.append(&mut entities.iter().collect::<Vec<_>>());
+    broad_phase_data.awake_entities.append(&mut entities.iter().collect::<Vec<_>>());
 }
 
 pub(crate) fn refresh_entities(
@@ -27,8 +31,7 @@ pub(crate) fn refresh_entities(
     config: Res<CollisionConfig>,
     entity_added: Query<Entity, Added<PhysicsAwake>>,
     entity_removed: RemovedComponents<PhysicsAwake>,
-    entity_changed: Query<Entity, Changed<Transform2d>>,
-    query: Query<(&Transform2d, &AABB), With<PolygonComponent>>,
+    query: Query<(&Transform2d, &AABB), ColliderFilter>,
     mut commands: Commands,
 )
 {
@@ -36,67 +39,115 @@ pub(crate) fn refresh_entities(
 
     // Filter existing entities
     let entity_removed = entity_removed.iter().collect::<Vec<_>>();
-    broad_phase_data.sorted_entities.retain(|x| !entity_removed.contains(&x));
-    broad_phase_data.sorted_entities.retain(|&x| query.get(x).is_ok());
+    broad_phase_data.awake_entities.retain(|x| !entity_removed.contains(&x));
+    broad_phase_data.awake_entities.retain(|&x| query.get(x).is_ok());
 
     // FIXME: Waking up isn't done properly, nearby object should be woken up too
     // Handle asleep/awake of entities
     // if config.system_params.put_non_colliding_asleep
     // {
-    //     for entity in &broad_phase_data.sorted_entities {
+    //     for entity in &broad_phase_data.awake_entities {
     //         if !narrow_phase_data.collided_entities.contains(&entity)
     //         {
     //             commands.entity(*entity).remove::<PhysicsAwake>();
     //         }
     //     }
-    //     broad_phase_data.sorted_entities.retain(|x| narrow_phase_data.collided_entities.contains(&x));
+    //     broad_phase_data.awake_entities.retain(|x| narrow_phase_data.collided_entities.contains(&x));
     // }
 
     let mut new_entities = entity_added.iter().collect::<Vec<_>>();
-    broad_phase_data.sorted_entities.append(&mut new_entities);
+    if !new_entities.is_empty()
+    {
+        // Entities added at startup are also reported by Added on the first frame
+        broad_phase_data.awake_entities.append(&mut new_entities);
+        broad_phase_data.awake_entities.sort();
+        broad_phase_data.awake_entities.dedup();
+    }
+}
+
+// Despawned entities, or the ones which lost their collider, can't be part of a pair anymore
+pub(crate) fn pair_exclusions_cleanup_system(
+    mut exclusions: ResMut<PairExclusions>,
+    polygon_removed: RemovedComponents<PolygonComponent>,
+    heightfield_removed: RemovedComponents<HeightfieldComponent>,
+)
+{
+    for entity in polygon_removed.iter().chain(heightfield_removed.iter()) {
+        // Only borrow mutably when needed, a change makes the broad phase check its pairs again
+        if exclusions.contains(entity) {
+            exclusions.remove_entity(entity);
+        }
+    }
+}
 
-    if !entity_added.is_empty() || !entity_removed.is_empty() || !entity_changed.is_empty()
+// Polygons spawned without a world space cache get one, world_polygon_update_system then keeps it up to date
+pub(crate) fn world_polygon_insert_system(
+    mut commands: Commands,
+    query: Query<(Entity, &PolygonComponent, &Transform2d), Without<WorldPolygon>>,
+)
+{
+    for (entity, p, t) in query.iter()
     {
-        broad_phase_data.sorted_entities.sort_by(|&e1, &e2| {
-            let (t1, a1) = query.get(e1).unwrap();
-            let (t2, a2) = query.get(e2).unwrap();
-            let a1_min_x = t1.translate(a1.min).x;
-            let a2_min_x = t2.translate(a2.min).x;
+        commands.entity(entity).insert(WorldPolygon::new(p, t));
+    }
+}
 
-            let cmp = (a2_min_x).total_cmp(&a1_min_x);
+// Refreshed along the AABBs, so the narrow phase reads the world space points instead of transforming them for every pair
+pub(crate) fn world_polygon_update_system(
+    mut query: Query<(&PolygonComponent, &mut WorldPolygon, &Transform2d), Or<(Changed<Transform2d>, Added<WorldPolygon>)>>,
+)
+{
+    for (p, mut w, t) in query.iter_mut()
+    {
+        w.update(&p, &t);
+    }
+}
 
-            if cmp.is_eq() {
-                return e1.cmp(&e2);
-            }
-            return cmp;
-        });
-        broad_phase_data.sorted_entities.dedup();
-        // warn!("{:?}", broad_phase_data.sorted_entities);
+pub(crate) fn aabb_update_system(
+    mut query: Query<(&PolygonComponent, &mut AABB, &Transform2d, Option<&RigidBody2d>), Changed<Transform2d>>,
+    config: Res<CollisionConfig>,
+)
+{
+    for (p, mut a, t, rigidbody) in query.iter_mut()
+    {
+        let tight = AABB::from_polygon(&p, &t);
+        a.min = tight.min;
+        a.max = tight.max;
+
+        // Keep the fat box as long as it contains the tight one, so the broad phase structures don't move
+        if a.needs_refit(&t)
+        {
+            let displacement = rigidbody.map_or(Vec2::ZERO, |r| r.linear_speed * TIMESTEP as f32 * config.phase_config.aabb_prediction_steps);
+            a.refit(&t, config.phase_config.aabb_margin, displacement);
+        }
     }
 }
 
-pub(crate) fn aabb_update_system(mut query: Query<(&PolygonComponent, &mut AABB, &Transform2d), Changed<Transform2d>>)
+pub(crate) fn heightfield_aabb_update_system(mut query: Query<(&HeightfieldComponent, &mut AABB, &Transform2d), Or<(Changed<Transform2d>, Changed<HeightfieldComponent>)>>)
 {
-    for (p, mut a, t) in query.iter_mut()
+    for (h, mut a, t) in query.iter_mut()
     {
-        *a = AABB::from(&p.get_rotated_points(&t));
+        *a = h.get_aabb(&t);
     }
 }
 
 pub fn update_rigidbodies(
-    mut query: Query<(&mut RigidBody2d, &mut Transform2d)>,
+    mut query: Query<(&mut RigidBody2d, &mut Transform2d, Option<&Ccd>)>,
     config: Res<CollisionConfig>,
     time: Res<Time>,
 )
 {
-    for (mut rigidbody, mut transform) in query.iter_mut() {
-        if !rigidbody.is_kinematic {
+    for (mut rigidbody, mut transform, ccd) in query.iter_mut() {
+        if !rigidbody.is_immovable() {
             let mut acceleration = rigidbody.linear_acceleration;
             if config.system_params.gravity_enabled {
                 acceleration += rigidbody.get_mass() * Vec2::new(0f32, -9.81f32);
             }
             rigidbody.linear_speed += acceleration * TIMESTEP as f32;
-            transform.translation += rigidbody.linear_speed * TIMESTEP as f32;
+            // Ccd bodies are swept to their new position by the continuous collision system
+            if ccd.is_none() || !config.phase_config.ccd_enabled {
+                transform.translation += rigidbody.linear_speed * TIMESTEP as f32;
+            }
             transform.rotation += rigidbody.angular_speed * TIMESTEP as f32;
         } else {
             rigidbody.linear_speed = Vec2::ZERO;
@@ -4,8 +4,11 @@ use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
 
 use crate::{DoNotDestroy, MainCamera, random_poly};
+use crate::collision_plugin::ccd::Ccd;
+use crate::collision_plugin::one_way_platform::OneWayPlatform;
 use crate::collision_plugin::plugin::CollisionStage;
 use crate::collision_plugin::polygon_component::PolygonComponent;
+use crate::collision_plugin::rigidbody::BodyType;
 use crate::random_poly::RandomPolyConfig;
 use crate::transform2d::Transform2d;
 
@@ -51,6 +54,14 @@ fn add_polygons(mut commands: Commands, keys: Res<Input<KeyCode>>,
         }
     }
 
+    if keys.just_pressed(KeyCode::B)
+    {
+        for _ in 0..10
+        {
+            commands.spawn((random_poly::create_random_poly(&config), Ccd));
+        }
+    }
+
     if keys.just_pressed(KeyCode::F1)
     {
         for entity in query.iter()
@@ -59,14 +70,21 @@ fn add_polygons(mut commands: Commands, keys: Res<Input<KeyCode>>,
         }
 
         // add back the borders that were destroyed (Usage of DoNotDestroy component somehow bug the whole system)
-        commands.spawn(random_poly::create_square(100f32, 1000f32, Vec2::new(-1101f32, 0f32), 0f32, true));
-        commands.spawn(random_poly::create_square(100f32, 1000f32, Vec2::new(1101f32, 0f32), 0f32, true));
-        commands.spawn(random_poly::create_square(1000f32, 100f32, Vec2::new(0f32, 1101f32), 0f32, true));
-        commands.spawn(random_poly::create_square(1000f32, 100f32, Vec2::new(0f32, -1101f32), 0f32, true));
+        commands.spawn(random_poly::create_square(100f32, 1000f32, Vec2::new(-1101f32, 0f32), 0f32, BodyType::Static));
+        commands.spawn(random_poly::create_square(100f32, 1000f32, Vec2::new(1101f32, 0f32), 0f32, BodyType::Static));
+        commands.spawn(random_poly::create_square(1000f32, 100f32, Vec2::new(0f32, 1101f32), 0f32, BodyType::Static));
+        commands.spawn(random_poly::create_square(1000f32, 100f32, Vec2::new(0f32, -1101f32), 0f32, BodyType::Static));
     }
 
     if keys.just_pressed(KeyCode::C) {
-        commands.spawn(random_poly::create_square(100f32, 20f32, Vec2::ZERO, 0f32, false));
+        commands.spawn(random_poly::create_square(100f32, 20f32, Vec2::ZERO, 0f32, BodyType::Dynamic));
+    }
+
+    if keys.just_pressed(KeyCode::P) {
+        commands.spawn((
+            random_poly::create_square(300f32, 10f32, transform.translation.xy(), 0f32, BodyType::Static),
+            OneWayPlatform::default(),
+        ));
     }
 }
 
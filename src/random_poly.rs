@@ -2,8 +2,9 @@ use std::default::Default;
 use bevy::prelude::*;
 
 use crate::collision_plugin::aabb::AABB;
+use crate::collision_plugin::heightfield_component::HeightfieldComponent;
 use crate::collision_plugin::PhysicsAwake;
-use crate::collision_plugin::rigidbody::RigidBody2d;
+use crate::collision_plugin::rigidbody::{BodyType, RigidBody2d};
 use crate::collision_plugin::polygon_component::PolygonComponent;
 use crate::transform2d::Transform2d;
 
@@ -60,7 +61,7 @@ pub fn create_random_poly(config: &RandomPolyConfig) -> (PolygonComponent, Trans
         rotation: rotation,
         scale: 1f32,
     };
-    let aabb = AABB::from(&polygon.get_rotated_points(&transform));
+    let aabb = AABB::from_polygon(&polygon, &transform);
 
     let rand_speed_x = {
         let min_x = config.min_linear_speed.x as i32;
@@ -78,7 +79,7 @@ pub fn create_random_poly(config: &RandomPolyConfig) -> (PolygonComponent, Trans
 
     let rigidbody = RigidBody2d {
         mass: (radius) as f32,
-        is_kinematic: false,
+        body_type: BodyType::Dynamic,
         linear_speed: Vec2::new(rand_speed_x as f32, rand_speed_y as f32),
         angular_speed: rand_angular_speed,
         ..Default::default()
@@ -87,7 +88,7 @@ pub fn create_random_poly(config: &RandomPolyConfig) -> (PolygonComponent, Trans
     return (polygon, transform, aabb, rigidbody, PhysicsAwake);
 }
 
-pub fn create_square(length: f32, height: f32, position: Vec2, rotation: f32, kinematic: bool) -> (PolygonComponent, Transform2d, AABB, RigidBody2d, PhysicsAwake) {
+pub fn create_square(length: f32, height: f32, position: Vec2, rotation: f32, body_type: BodyType) -> (PolygonComponent, Transform2d, AABB, RigidBody2d, PhysicsAwake) {
     let mut points = Vec::with_capacity(4);
 
     points.push(Vec2::new(-length, -height));
@@ -101,14 +102,28 @@ pub fn create_square(length: f32, height: f32, position: Vec2, rotation: f32, ki
         rotation: rotation,
         scale: 1f32,
     };
-    let aabb = AABB::from(&polygon.get_rotated_points(&transform));
+    let aabb = AABB::from_polygon(&polygon, &transform);
     let mut rigidbody = RigidBody2d::default();
-    rigidbody.is_kinematic = kinematic;
+    rigidbody.body_type = body_type;
     rigidbody.mass = length * height;
 
     return (polygon, transform, aabb, rigidbody, PhysicsAwake);
 }
 
+pub fn create_heightfield(heights: Vec<f32>, cell_width: f32, position: Vec2) -> (HeightfieldComponent, Transform2d, AABB, RigidBody2d, PhysicsAwake) {
+    let heightfield = HeightfieldComponent::new(heights, cell_width);
+    let transform = Transform2d {
+        translation: position,
+        rotation: 0f32,
+        scale: 1f32,
+    };
+    let aabb = heightfield.get_aabb(&transform);
+    let mut rigidbody = RigidBody2d::default();
+    rigidbody.body_type = BodyType::Static;
+
+    return (heightfield, transform, aabb, rigidbody, PhysicsAwake);
+}
+
 impl Default for RandomPolyConfig {
     fn default() -> Self {
         RandomPolyConfig {
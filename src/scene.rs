@@ -1,5 +1,6 @@
 use bevy::prelude::*;
 use crate::{MainCamera, random_poly};
+use crate::collision_plugin::rigidbody::BodyType;
 use crate::random_poly::RandomPolyConfig;
 
 pub fn setup_scene(mut commands: Commands)
@@ -14,14 +15,18 @@ fn startup_add_polygons(commands: &mut Commands)
 
     let config = RandomPolyConfig::default();
 
-    commands.spawn(random_poly::create_square(100f32, 1000f32, Vec2::new(-1101f32, 0f32), 0f32, true));
-    commands.spawn(random_poly::create_square(100f32, 1000f32, Vec2::new(1101f32, 0f32), 0f32, true));
-    commands.spawn(random_poly::create_square(1000f32, 100f32, Vec2::new(0f32, 1101f32), 0f32, true));
-    commands.spawn(random_poly::create_square(1000f32, 100f32, Vec2::new(0f32, -1101f32), 0f32, true));
+    commands.spawn(random_poly::create_square(100f32, 1000f32, Vec2::new(-1101f32, 0f32), 0f32, BodyType::Static));
+    commands.spawn(random_poly::create_square(100f32, 1000f32, Vec2::new(1101f32, 0f32), 0f32, BodyType::Static));
+    commands.spawn(random_poly::create_square(1000f32, 100f32, Vec2::new(0f32, 1101f32), 0f32, BodyType::Static));
+    commands.spawn(random_poly::create_square(1000f32, 100f32, Vec2::new(0f32, -1101f32), 0f32, BodyType::Static));
+
+    // Hills laid on the floor
+    let heights = (0..=40).map(|i| (i as f32 * 0.5f32).sin().abs() * 80f32).collect();
+    commands.spawn(random_poly::create_heightfield(heights, 50f32, Vec2::new(-1000f32, -1001f32)));
 
     for _ in 0..2000
     {
-        // commands.spawn(random_poly::create_square(100f32, 20f32, Vec2::ZERO, 0f32, false));
+        // commands.spawn(random_poly::create_square(100f32, 20f32, Vec2::ZERO, 0f32, BodyType::Dynamic));
         commands.spawn(random_poly::create_random_poly(&config));
     }
 }